@@ -203,7 +203,7 @@ fn main() {
 
                     })
                     .attach(Position {
-                        x: x,
+                        x,
                         y: 0.0,
                     })
                     .attach(Health {
@@ -0,0 +1,63 @@
+use std::io;
+use crate::invalid;
+
+/// Compression applied to every section of a registry stream, set with `Registry::set_codec`.
+/// Sections are compressed on their own, `Registry::deserialize_from` keeps component sections
+/// compressed until their storage is first accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Codec {
+    #[default]
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "deflate")]
+    Deflate
+}
+
+impl Codec {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => 1,
+            #[cfg(feature = "deflate")]
+            Codec::Deflate => 2
+        }
+    }
+
+    pub(crate) fn from_byte(byte:u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(Codec::None),
+            #[cfg(feature = "lz4")]
+            1 => Ok(Codec::Lz4),
+            #[cfg(not(feature = "lz4"))]
+            1 => Err(invalid("registry stream is lz4 compressed, enable the `lz4` feature")),
+            #[cfg(feature = "deflate")]
+            2 => Ok(Codec::Deflate),
+            #[cfg(not(feature = "deflate"))]
+            2 => Err(invalid("registry stream is deflate compressed, enable the `deflate` feature")),
+            _ => Err(invalid(format!("unknown codec {}", byte)))
+        }
+    }
+
+    pub(crate) fn compress(self, bytes:Vec<u8>) -> Vec<u8> {
+        match self {
+            Codec::None => bytes,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => lz4_flex::compress_prepend_size(&bytes),
+            #[cfg(feature = "deflate")]
+            Codec::Deflate => miniz_oxide::deflate::compress_to_vec(&bytes, 6)
+        }
+    }
+
+    pub(crate) fn decompress(self, bytes:Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Codec::None => Ok(bytes),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => lz4_flex::decompress_size_prepended(&bytes).map_err(invalid),
+            #[cfg(feature = "deflate")]
+            Codec::Deflate => miniz_oxide::inflate::decompress_to_vec(&bytes).map_err(|err| invalid(format!("{:?}", err)))
+        }
+    }
+}
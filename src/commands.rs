@@ -1,15 +1,25 @@
 use crate::Registry;
 
+type Command = Box<dyn Fn(&mut Registry)>;
+
 #[derive(Default)]
 pub struct Commands {
-    commands:Vec<Box<dyn Fn(&mut Registry) -> ()>>
+    commands:Vec<Command>
 }
 
 impl Commands {
-    pub fn push<T:Fn(&mut Registry) -> () + 'static>(&mut self, f:T) {
+    pub fn push<T:Fn(&mut Registry) + 'static>(&mut self, f:T) {
         self.commands.push(Box::new(f));
     }
 
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
     pub fn execute(&mut self, registry:&mut Registry) {
         for command in self.commands.drain(..) {
             command(registry);
@@ -1,5 +1,17 @@
 use serde::{Serialize, de::DeserializeOwned};
 
-pub trait Component : Default + Serialize + DeserializeOwned + 'static + Clone {
+pub trait Component : 'static {
     fn type_id() -> uuid::Uuid;
-}
\ No newline at end of file
+
+    /// Components inserted with their default value when this component is attached without them.
+    fn required() -> Vec<uuid::Uuid> {
+        Vec::new()
+    }
+}
+
+/// Components that are saved by `Registry::serialize`, registered with `register_component`.
+pub trait SerializableComponent : Component + Default + Serialize + DeserializeOwned + Clone {
+}
+
+impl<T:Component + Default + Serialize + DeserializeOwned + Clone> SerializableComponent for T {
+}
@@ -1,65 +1,232 @@
-use std::cell::{RefCell, Ref, RefMut};
+use std::cell::{Cell, RefCell, Ref, RefMut};
+use std::fmt::Display;
+use std::{slice, vec};
 use slotmap::SecondaryMap;
-use crate::{EntityId, Storage, Component};
+use crate::{EntityId, EntityIter, Storage, Component};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorrowError {
+    Missing,
+    Borrowed
+}
 
-pub struct Components<'a, T:Component> {
-    storage:&'a SecondaryMap<EntityId, RefCell<T>>
+impl Display for BorrowError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BorrowError::Missing => write!(f, "component missing"),
+            BorrowError::Borrowed => write!(f, "component already borrowed")
+        }
+    }
 }
 
-impl<'a, T:Component> Components<'a, T> {
-    pub unsafe fn new(storage:&'a Storage) -> Self {
-        let storage = storage.get();
+impl std::error::Error for BorrowError {
+}
+
+/// Counts borrow conflicts of a storage and panics on them in strict mode.
+#[derive(Clone, Copy)]
+pub(crate) struct Conflicts<'a> {
+    name:&'a str,
+    skipped:&'a Cell<usize>,
+    pub(crate) strict:bool
+}
+
+impl<'a> Conflicts<'a> {
+    pub(crate) fn new(storage:&'a Storage, strict:bool) -> Self {
         Self {
-            storage
+            name:&storage.name,
+            skipped:&storage.skipped,
+            strict
         }
     }
 
-    pub fn get(&self, id:EntityId) -> Option<Ref<T>> {
-        if let Some(c) = self.storage.get(id) {
-            if let Ok(c) = c.try_borrow() {
-                return Some(c);
-            }
+    pub(crate) fn report(&self, id:EntityId) {
+        self.skipped.set(self.skipped.get() + 1);
+        if self.strict && cfg!(debug_assertions) {
+            panic!("{} of {:?} is already borrowed", self.name, id);
         }
+    }
+}
 
-        None
+/// Visits the cells of a storage in slot order or in the order set by `Registry::sort`.
+enum Cells<'a, T> {
+    Slots(slotmap::secondary::Iter<'a, EntityId, RefCell<T>>),
+    Sorted(slice::Iter<'a, EntityId>, &'a SecondaryMap<EntityId, RefCell<T>>),
+    Keyed(vec::IntoIter<EntityId>, &'a SecondaryMap<EntityId, RefCell<T>>)
+}
+
+impl<'a, T> Iterator for Cells<'a, T> {
+    type Item = (EntityId, &'a RefCell<T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Cells::Slots(iter) => iter.next(),
+            Cells::Sorted(ids, map) => ids.find_map(|id| Some((*id, map.get(*id)?))),
+            Cells::Keyed(ids, map) => ids.find_map(|id| Some((id, map.get(id)?)))
+        }
     }
+}
 
-    pub fn get_mut(&self, id:EntityId) -> Option<RefMut<T>> {
-        if let Some(c) = self.storage.get(id) {
-            if let Ok(c) = c.try_borrow_mut() {
-                return Some(c);
-            }
+pub struct Components<'a, T:Component> {
+    storage:&'a SecondaryMap<EntityId, RefCell<T>>,
+    order:Option<&'a [EntityId]>,
+    touched:Option<&'a Storage>,
+    disabled:&'a SecondaryMap<EntityId, ()>,
+    pub(crate) conflicts:Conflicts<'a>
+}
+
+impl<'a, T:Component> Components<'a, T> {
+    pub(crate) fn new(storage:&'a Storage, disabled:&'a SecondaryMap<EntityId, ()>) -> Self {
+        let touched = if storage.tracked || storage.journaled { Some(storage) } else { None };
+        let conflicts = Conflicts::new(storage, false);
+        let order = storage.order();
+        let storage = storage.typed();
+        Self {
+            storage,
+            order,
+            touched,
+            disabled,
+            conflicts
         }
+    }
 
-        None
+    pub fn get(&self, id:EntityId) -> Option<Ref<'_, T>> {
+        self.try_get(id).ok()
+    }
+
+    pub fn get_mut(&self, id:EntityId) -> Option<RefMut<'_, T>> {
+        self.try_get_mut(id).ok()
+    }
+
+    pub fn try_get(&self, id:EntityId) -> Result<Ref<'_, T>, BorrowError> {
+        let cell = self.storage.get(id).ok_or(BorrowError::Missing)?;
+        cell.try_borrow().map_err(|_| {
+            self.conflicts.report(id);
+            BorrowError::Borrowed
+        })
+    }
+
+    pub fn try_get_mut(&self, id:EntityId) -> Result<RefMut<'_, T>, BorrowError> {
+        let cell = self.storage.get(id).ok_or(BorrowError::Missing)?;
+        let component = cell.try_borrow_mut().map_err(|_| {
+            self.conflicts.report(id);
+            BorrowError::Borrowed
+        })?;
+        if let Some(touched) = self.touched {
+            touched.touch(id);
+        }
+        Ok(component)
     }
 
     pub fn iter(&self) -> Iter<'a, T> {
-        let iter = self.storage.iter();
+        let iter = self.cells();
         Iter {
             iter,
+            disabled:self.enabled_only(),
+            conflicts:self.conflicts
         }
     }
 
     pub fn iter_mut(&self) -> IterMut<'a, T> {
-        let iter = self.storage.iter();
+        let iter = self.cells();
+        IterMut {
+            iter,
+            touched:self.touched,
+            disabled:self.enabled_only(),
+            conflicts:self.conflicts
+        }
+    }
+
+    pub fn iter_all(&self) -> Iter<'a, T> {
+        let iter = self.cells();
+        Iter {
+            iter,
+            disabled:None,
+            conflicts:self.conflicts
+        }
+    }
+
+    pub fn iter_mut_all(&self) -> IterMut<'a, T> {
+        let iter = self.cells();
         IterMut {
             iter,
+            touched:self.touched,
+            disabled:None,
+            conflicts:self.conflicts
+        }
+    }
+
+    /// Like `iter` but yields borrow conflicts instead of skipping them.
+    pub fn try_iter(&self) -> TryIter<'a, T> {
+        TryIter {
+            iter:self.cells(),
+            disabled:self.enabled_only()
         }
     }
+
+    /// Like `iter_mut` but yields borrow conflicts instead of skipping them.
+    pub fn try_iter_mut(&self) -> TryIterMut<'a, T> {
+        TryIterMut {
+            iter:self.cells(),
+            touched:self.touched,
+            disabled:self.enabled_only()
+        }
+    }
+
+    /// Like `iter` but ordered by `key`, which sorts on every call. `Registry::sort` keeps the order
+    /// in the storage instead, which is cheaper when iterating in the same order every frame.
+    pub fn iter_sorted_by_key<K:Ord, F:FnMut(&T) -> K>(&self, mut key:F) -> Iter<'a, T> {
+        let mut keyed:Vec<(K, EntityId)> = Vec::with_capacity(self.storage.len());
+        for (id, cell) in self.storage.iter() {
+            match cell.try_borrow() {
+                Ok(value) => keyed.push((key(&value), id)),
+                Err(_) => self.conflicts.report(id)
+            }
+        }
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let ids:Vec<EntityId> = keyed.into_iter().map(|(_, id)| id).collect();
+        Iter {
+            iter:Cells::Keyed(ids.into_iter(), self.storage),
+            disabled:self.enabled_only(),
+            conflicts:self.conflicts
+        }
+    }
+
+    fn cells(&self) -> Cells<'a, T> {
+        match self.order {
+            Some(order) => Cells::Sorted(order.iter(), self.storage),
+            None => Cells::Slots(self.storage.iter())
+        }
+    }
+
+    /// Number of entities skipped because of borrow conflicts since the storage was created.
+    pub fn skipped(&self) -> usize {
+        self.conflicts.skipped.get()
+    }
+
+    fn enabled_only(&self) -> Option<&'a SecondaryMap<EntityId, ()>> {
+        if self.disabled.is_empty() {
+            return None;
+        }
+        Some(self.disabled)
+    }
 }
 
 pub struct Iter<'a, T:Component> {
-    iter:slotmap::secondary::Iter<'a, EntityId, RefCell<T>>
+    iter:Cells<'a, T>,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>,
+    conflicts:Conflicts<'a>
 }
 
 impl<'a, T:Component> Iterator for Iter<'a, T> {
     type Item = (EntityId, Ref<'a, T>);
     fn next(&mut self) -> Option<Self::Item> {
         for (id, cell) in self.iter.by_ref() {
-            if let Ok(value) = cell.try_borrow() {
-                return Some((id, value));
+            if self.disabled.is_some_and(|disabled| disabled.contains_key(id)) {
+                continue;
+            }
+            match cell.try_borrow() {
+                Ok(value) => return Some((id, value)),
+                Err(_) => self.conflicts.report(id)
             }
         }
 
@@ -68,18 +235,117 @@ impl<'a, T:Component> Iterator for Iter<'a, T> {
 }
 
 pub struct IterMut<'a, T:Component> {
-    iter:slotmap::secondary::Iter<'a, EntityId, RefCell<T>>
+    iter:Cells<'a, T>,
+    touched:Option<&'a Storage>,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>,
+    conflicts:Conflicts<'a>
 }
 
 impl<'a, T:Component> Iterator for IterMut<'a, T> {
     type Item = (EntityId, RefMut<'a, T>);
     fn next(&mut self) -> Option<Self::Item> {
         for (id, cell) in self.iter.by_ref() {
-            if let Ok(value) = cell.try_borrow_mut() {
-                return Some((id, value));
+            if self.disabled.is_some_and(|disabled| disabled.contains_key(id)) {
+                continue;
+            }
+            match cell.try_borrow_mut() {
+                Ok(value) => {
+                    if let Some(touched) = self.touched {
+                        touched.touch(id);
+                    }
+                    return Some((id, value));
+                },
+                Err(_) => self.conflicts.report(id)
+            }
+        }
+
+        None
+    }
+}
+
+pub struct TryIter<'a, T:Component> {
+    iter:Cells<'a, T>,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>
+}
+
+impl<'a, T:Component> Iterator for TryIter<'a, T> {
+    type Item = (EntityId, Result<Ref<'a, T>, BorrowError>);
+    fn next(&mut self) -> Option<Self::Item> {
+        for (id, cell) in self.iter.by_ref() {
+            if self.disabled.is_some_and(|disabled| disabled.contains_key(id)) {
+                continue;
+            }
+            return Some((id, cell.try_borrow().map_err(|_| BorrowError::Borrowed)));
+        }
+
+        None
+    }
+}
+
+pub struct TryIterMut<'a, T:Component> {
+    iter:Cells<'a, T>,
+    touched:Option<&'a Storage>,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>
+}
+
+impl<'a, T:Component> Iterator for TryIterMut<'a, T> {
+    type Item = (EntityId, Result<RefMut<'a, T>, BorrowError>);
+    fn next(&mut self) -> Option<Self::Item> {
+        for (id, cell) in self.iter.by_ref() {
+            if self.disabled.is_some_and(|disabled| disabled.contains_key(id)) {
+                continue;
+            }
+            let value = cell.try_borrow_mut().map_err(|_| BorrowError::Borrowed);
+            if value.is_ok() {
+                if let Some(touched) = self.touched {
+                    touched.touch(id);
+                }
             }
+            return Some((id, value));
         }
 
         None
     }
-}
\ No newline at end of file
+}
+enum SortedIds<'a> {
+    Sorted(slice::Iter<'a, EntityId>),
+    Unsorted(EntityIter<'a>)
+}
+
+/// Enabled entities holding a component, in the order set by `Registry::sort` or in slot order
+/// if the storage was not sorted or changed since.
+pub struct SortedIter<'a> {
+    ids:SortedIds<'a>,
+    storage:&'a Storage,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>
+}
+
+impl<'a> SortedIter<'a> {
+    pub(crate) fn new(storage:&'a Storage, entities:EntityIter<'a>, disabled:Option<&'a SecondaryMap<EntityId, ()>>) -> Self {
+        let ids = match storage.order() {
+            Some(order) => SortedIds::Sorted(order.iter()),
+            None => SortedIds::Unsorted(entities)
+        };
+        Self {
+            ids,
+            storage,
+            disabled
+        }
+    }
+}
+
+impl Iterator for SortedIter<'_> {
+    type Item = EntityId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let id = match &mut self.ids {
+                SortedIds::Sorted(ids) => *ids.next()?,
+                SortedIds::Unsorted(ids) => ids.find(|id| self.storage.has(*id))?
+            };
+            if !self.disabled.is_some_and(|disabled| disabled.contains_key(id)) {
+                return Some(id);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::{Display, Formatter, Result};
+use uuid::Uuid;
+use crate::{Component, EntityId, Registry, Storage};
+
+pub struct Dump<'a> {
+    registry:&'a Registry,
+    components:Option<Vec<Uuid>>,
+    entities:Option<Vec<EntityId>>
+}
+
+impl<'a> Dump<'a> {
+    pub fn new(registry:&'a Registry) -> Self {
+        Self {
+            registry,
+            components:None,
+            entities:None
+        }
+    }
+
+    pub fn component<T:Component>(self) -> Self {
+        self.component_id(T::type_id())
+    }
+
+    pub fn component_id(mut self, id:Uuid) -> Self {
+        self.components.get_or_insert_with(Vec::new).push(id);
+        self
+    }
+
+    pub fn entity(mut self, id:EntityId) -> Self {
+        self.entities.get_or_insert_with(Vec::new).push(id);
+        self
+    }
+
+    fn sorted<'b>(storages:impl Iterator<Item = (&'b Uuid, &'b Storage)>) -> Vec<(&'b Uuid, &'b Storage)> {
+        let mut storages:Vec<_> = storages.collect();
+        storages.sort_by(|(a_id, a), (b_id, b)| a.name.cmp(&b.name).then(a_id.cmp(b_id)));
+        storages
+    }
+
+    fn included(&self, id:&Uuid) -> bool {
+        match &self.components {
+            Some(components) => components.contains(id),
+            None => true
+        }
+    }
+}
+
+impl Display for Dump<'_> {
+    fn fmt(&self, f:&mut Formatter<'_>) -> Result {
+        let storages:Vec<_> = Self::sorted(self.registry.storages()).into_iter().filter(|(id, _)| self.included(id)).collect();
+        let singletons:Vec<_> = Self::sorted(self.registry.singleton_storages()).into_iter().filter(|(id, _)| self.included(id)).collect();
+        writeln!(f, "Registry {{ entities: {}, components: {}, singletons: {} }}", self.registry.len(), storages.len(), singletons.len())?;
+
+        writeln!(f, "Storages:")?;
+        for (_, storage) in storages.iter() {
+            writeln!(f, "  {}: {}", storage.name, storage.len())?;
+        }
+
+        writeln!(f, "Singletons:")?;
+        let singleton = self.registry.singleton_id();
+        for (_, storage) in singletons.iter() {
+            if let Some(value) = storage.debug(singleton) {
+                writeln!(f, "  {}: {}", storage.name, value)?;
+            }
+        }
+
+        writeln!(f, "Entities:")?;
+        for id in self.registry.iter_all() {
+            if let Some(entities) = &self.entities {
+                if !entities.contains(&id) {
+                    continue;
+                }
+            }
+            let attached:Vec<_> = storages.iter().filter(|(_, storage)| storage.has(id)).collect();
+            if attached.is_empty() && self.components.is_some() {
+                continue;
+            }
+            match self.registry.is_enabled(id) {
+                true => writeln!(f, "  {}", self.registry.describe(id))?,
+                false => writeln!(f, "  {} (disabled)", self.registry.describe(id))?
+            }
+            for (_, storage) in attached {
+                if let Some(value) = storage.debug(id) {
+                    writeln!(f, "    {}: {}", storage.name, value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use slotmap::SecondaryMap;
+use crate::{put_boxed, shrink, slot_size, take_boxed, EntityId, ErasedStorage, ReflectError, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    Bool,
+    Int,
+    Float,
+    String,
+    Any
+}
+
+impl FieldKind {
+    pub fn default_value(&self) -> Value {
+        match self {
+            FieldKind::Bool => Value::Bool(false),
+            FieldKind::Int => Value::from(0),
+            FieldKind::Float => Value::from(0.0),
+            FieldKind::String => Value::String(String::new()),
+            FieldKind::Any => Value::Null
+        }
+    }
+
+    pub fn accepts(&self, value:&Value) -> bool {
+        match self {
+            FieldKind::Bool => value.is_boolean(),
+            FieldKind::Int => value.is_i64() || value.is_u64(),
+            FieldKind::Float => value.is_number(),
+            FieldKind::String => value.is_string(),
+            FieldKind::Any => true
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name:String,
+    pub kind:FieldKind
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub name:String,
+    pub fields:Vec<Field>
+}
+
+impl Schema {
+    pub fn new(name:&str) -> Self {
+        Self {
+            name:name.to_string(),
+            fields:Vec::new()
+        }
+    }
+
+    pub fn field(mut self, name:&str, kind:FieldKind) -> Self {
+        self.fields.push(Field {
+            name:name.to_string(),
+            kind
+        });
+        self
+    }
+
+    pub fn default_value(&self) -> Value {
+        let mut object = Map::new();
+        for field in self.fields.iter() {
+            object.insert(field.name.clone(), field.kind.default_value());
+        }
+        Value::Object(object)
+    }
+
+    /// Checks `value` against the schema, filling in missing fields with their defaults.
+    pub fn conform(&self, value:Value) -> Result<Value, ReflectError> {
+        let Value::Object(mut object) = value else {
+            return Err(ReflectError::Invalid(format!("{} expects an object", self.name)));
+        };
+        for key in object.keys() {
+            if !self.fields.iter().any(|field| &field.name == key) {
+                return Err(ReflectError::Invalid(format!("{} has no field {}", self.name, key)));
+            }
+        }
+        for field in self.fields.iter() {
+            match object.get(&field.name) {
+                Some(value) => {
+                    if !field.kind.accepts(value) {
+                        return Err(ReflectError::Invalid(format!("{}.{} expects {:?}", self.name, field.name, field.kind)));
+                    }
+                },
+                None => {
+                    object.insert(field.name.clone(), field.kind.default_value());
+                }
+            }
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+/// Values are serialized as json since bincode can not deserialize `Value`. The layout is that of a
+/// bincode `Vec<(EntityId, Vec<u8>)>` holding the json of each value, written one entry at a time.
+struct DynamicStorage {
+    schema:Schema,
+    map:SecondaryMap<EntityId, RefCell<Value>>
+}
+
+/// Counts the bytes written to it, to size entries without buffering them.
+#[derive(Default)]
+struct ByteCount(u64);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf:&[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn json_error(err:serde_json::Error) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(err.to_string()))
+}
+
+impl DynamicStorage {
+    fn json_len(cell:&RefCell<Value>) -> u64 {
+        let mut count = ByteCount::default();
+        serde_json::to_writer(&mut count, cell).expect("failed to serialize");
+        count.0
+    }
+}
+
+impl ErasedStorage for DynamicStorage {
+    fn map(&self) -> &dyn Any {
+        &self.map
+    }
+
+    fn map_mut(&mut self) -> &mut dyn Any {
+        &mut self.map
+    }
+
+    fn serialized_size(&self) -> u64 {
+        self.map.iter().fold(8, |size, (id, cell)| {
+            size + bincode::serialized_size(&id).expect("failed to serialize") + 8 + Self::json_len(cell)
+        })
+    }
+
+    fn serialize(&self, writer:&mut dyn Write) -> bincode::Result<()> {
+        bincode::serialize_into(&mut *writer, &(self.map.len() as u64))?;
+        for (id, cell) in self.map.iter() {
+            bincode::serialize_into(&mut *writer, &(id, Self::json_len(cell)))?;
+            serde_json::to_writer(&mut *writer, cell).map_err(json_error)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize(&mut self, reader:&mut dyn Read) -> bincode::Result<()> {
+        let len:u64 = bincode::deserialize_from(&mut *reader)?;
+        let mut map = SecondaryMap::new();
+        for _ in 0..len {
+            let (id, json_len):(EntityId, u64) = bincode::deserialize_from(&mut *reader)?;
+            let value = serde_json::from_reader((&mut *reader).take(json_len)).map_err(json_error)?;
+            map.insert(id, RefCell::new(value));
+        }
+        self.map = map;
+        Ok(())
+    }
+
+    fn serialize_one(&self, id:EntityId) -> Option<Vec<u8>> {
+        self.map.get(id).map(|cell| serde_json::to_vec(cell).expect("failed to serialize"))
+    }
+
+    fn deserialize_one(&mut self, id:EntityId, bytes:&[u8]) -> bincode::Result<()> {
+        let value:Value = serde_json::from_slice(bytes).map_err(json_error)?;
+        match self.map.get_mut(id) {
+            Some(cell) => {
+                cell.replace(value);
+            },
+            None => {
+                self.map.insert(id, RefCell::new(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id:EntityId) {
+        self.map.remove(id);
+    }
+
+    fn take(&mut self, id:EntityId) -> Option<Box<dyn Any>> {
+        take_boxed(&mut self.map, id)
+    }
+
+    fn put(&mut self, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        put_boxed(&mut self.map, id, component)
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            schema:self.schema.clone(),
+            map:self.map.clone()
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn clone_empty(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            schema:self.schema.clone(),
+            map:SecondaryMap::new()
+        })
+    }
+
+    fn default(&mut self, id:EntityId) {
+        if let Some(v) = self.map.get_mut(id) {
+            v.replace(self.schema.default_value());
+        }
+    }
+
+    fn insert_default(&mut self, id:EntityId) -> bool {
+        self.map.insert(id, RefCell::new(self.schema.default_value()));
+        true
+    }
+
+    fn has_default(&self) -> bool {
+        true
+    }
+
+    fn has(&self, id:EntityId) -> bool {
+        self.map.contains_key(id)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    fn reserve(&mut self, capacity:usize) {
+        self.map.set_capacity(capacity);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        shrink(&mut self.map);
+    }
+
+    fn slot_size(&self) -> usize {
+        slot_size::<Value>()
+    }
+
+    fn reflect_get(&self, id:EntityId) -> Option<Value> {
+        let value = self.map.get(id)?.try_borrow().ok()?;
+        Some(value.clone())
+    }
+
+    fn reflect_set(&mut self, id:EntityId, value:Value) -> Result<(), ReflectError> {
+        let value = self.schema.conform(value)?;
+        match self.map.get(id) {
+            Some(cell) => {
+                let mut current = cell.try_borrow_mut().map_err(|_| ReflectError::Borrowed)?;
+                *current = value;
+            },
+            None => {
+                self.map.insert(id, RefCell::new(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn reflect_default(&self) -> Value {
+        self.schema.default_value()
+    }
+}
+
+impl Storage {
+    pub fn new_dynamic(schema:Schema) -> Self {
+        let name = schema.name.clone();
+        let inner = DynamicStorage {
+            schema,
+            map:SecondaryMap::new()
+        };
+        Self::from_erased(name, Box::new(inner), true)
+    }
+}
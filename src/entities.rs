@@ -1,18 +1,306 @@
-use slotmap::basic::Keys;
-use slotmap::new_key_type;
+use std::iter::Enumerate;
+use std::mem::size_of;
+use std::slice::Iter;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use serde::{Serialize, Deserialize};
+use slotmap::{new_key_type, Key, KeyData, SecondaryMap};
 
 new_key_type! {
     pub struct EntityId;
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Meta {
+    version:u32,
+    alive:bool
+}
+
+/// Generational id allocator handing out `EntityId`s that are compatible with slotmap's
+/// `SecondaryMap`. Ids can be reserved through a shared reference, reserved ids are
+/// taken from the free list first and become alive on `flush`. The reservation cursor is atomic
+/// so concurrent reservations never hand out the same id.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Entities {
+    meta:Vec<Meta>,
+    free:Vec<u32>,
+    len:usize,
+    #[serde(skip)]
+    free_cursor:AtomicIsize
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self {
+            // index 0 is never used, matching slotmap's sentinel slot
+            meta:vec![Meta { version:1, alive:false }],
+            free:Vec::new(),
+            len:0,
+            free_cursor:AtomicIsize::new(0)
+        }
+    }
+}
+
+impl Clone for Entities {
+    fn clone(&self) -> Self {
+        Self {
+            meta:self.meta.clone(),
+            free:self.free.clone(),
+            len:self.len,
+            free_cursor:AtomicIsize::new(self.free_cursor.load(Ordering::Relaxed))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Slot {
+    value:Option<()>,
+    version:u32
+}
+
+/// Entities in the layout of slotmap's `SlotMap<EntityId, ()>`, which `Registry::serialize` wrote
+/// before the allocator replaced it. Keeping it lets older saves load and older readers load new ones.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntitySlots(Vec<Slot>);
+
+impl From<&Entities> for EntitySlots {
+    fn from(entities:&Entities) -> Self {
+        let slots = entities.meta.iter().enumerate().map(|(idx, meta)| match (idx, meta.alive) {
+            (0, _) => Slot { value:None, version:0 },
+            (_, true) => Slot { value:Some(()), version:meta.version },
+            // vacant slots have the even version before the one they hand out next
+            (_, false) => Slot { value:None, version:meta.version - 1 }
+        });
+        Self(slots.collect())
+    }
+}
+
+impl From<EntitySlots> for Entities {
+    fn from(slots:EntitySlots) -> Self {
+        let mut entities = Entities::default();
+        for (idx, slot) in slots.0.into_iter().enumerate().skip(1) {
+            let alive = slot.value.is_some();
+            entities.meta.push(Meta { version:slot.version | 1, alive });
+            match alive {
+                true => entities.len += 1,
+                false => entities.free.push(idx as u32)
+            }
+        }
+        entities.reset_cursor();
+        entities
+    }
+}
+
+fn id(idx:u32, version:u32) -> EntityId {
+    KeyData::from_ffi((u64::from(version) << 32) | u64::from(idx)).into()
+}
+
+fn split(id:EntityId) -> (u32, u32) {
+    let ffi = id.data().as_ffi();
+    ((ffi & 0xffff_ffff) as u32, (ffi >> 32) as u32)
+}
+
+impl Entities {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of indices handed out so far, alive or not.
+    pub(crate) fn slots(&self) -> usize {
+        self.meta.len() - 1
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.meta.capacity() - 1
+    }
+
+    pub(crate) fn free_len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Number of ids reserved since the last `flush`.
+    pub(crate) fn pending(&self) -> usize {
+        (self.free.len() as isize - self.free_cursor.load(Ordering::Relaxed)) as usize
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.meta.capacity() * size_of::<Meta>() + self.free.capacity() * size_of::<u32>()
+    }
+
+    pub(crate) fn reserve_slots(&mut self, additional:usize) {
+        self.meta.reserve(additional);
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.meta.shrink_to_fit();
+        self.free.shrink_to_fit();
+    }
+
+    pub(crate) fn contains(&self, id:EntityId) -> bool {
+        let (idx, version) = split(id);
+        match self.meta.get(idx as usize) {
+            Some(meta) => meta.alive && meta.version == version,
+            None => false
+        }
+    }
+
+    pub(crate) fn reserve(&self) -> EntityId {
+        let n = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        if n > 0 {
+            let idx = self.free[n as usize - 1];
+            id(idx, self.meta[idx as usize].version)
+        } else {
+            id((self.meta.len() as isize - n) as u32, 1)
+        }
+    }
+
+    pub(crate) fn reserve_many(&self, count:usize) -> Vec<EntityId> {
+        let count = count as isize;
+        let end = self.free_cursor.fetch_sub(count, Ordering::Relaxed);
+        let start = end - count;
+        (start..end).rev().map(|n| {
+            if n >= 0 {
+                let idx = self.free[n as usize];
+                id(idx, self.meta[idx as usize].version)
+            } else {
+                id((self.meta.len() as isize - n - 1) as u32, 1)
+            }
+        }).collect()
+    }
+
+    /// Ids reserved since the last `flush`, in the order `flush` makes them alive.
+    pub(crate) fn reserved(&self) -> impl Iterator<Item = EntityId> + '_ {
+        let cursor = self.free_cursor.load(Ordering::Relaxed);
+        let len = self.meta.len();
+        let free = self.free[cursor.max(0) as usize..].iter().map(|idx| id(*idx, self.meta[*idx as usize].version));
+        free.chain((0..(-cursor).max(0) as usize).map(move |n| id((len + n) as u32, 1)))
+    }
+
+    /// Makes all reserved ids alive.
+    pub(crate) fn flush(&mut self) {
+        let cursor = *self.free_cursor.get_mut();
+        if cursor < 0 {
+            for idx in self.free.drain(..) {
+                self.meta[idx as usize].alive = true;
+                self.len += 1;
+            }
+            for _ in 0..-cursor {
+                self.meta.push(Meta { version:1, alive:true });
+                self.len += 1;
+            }
+        } else {
+            for idx in self.free.drain(cursor as usize..) {
+                self.meta[idx as usize].alive = true;
+                self.len += 1;
+            }
+        }
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+    }
+
+    pub(crate) fn alloc(&mut self) -> EntityId {
+        self.flush();
+        let id = match self.free.pop() {
+            Some(idx) => {
+                let meta = &mut self.meta[idx as usize];
+                meta.alive = true;
+                id(idx, meta.version)
+            },
+            None => {
+                self.meta.push(Meta { version:1, alive:true });
+                id(self.meta.len() as u32 - 1, 1)
+            }
+        };
+        self.len += 1;
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+        id
+    }
+
+    /// Makes exactly `id` alive, replacing an older version in its slot. Used to replay journals.
+    pub(crate) fn alloc_at(&mut self, id:EntityId) {
+        self.flush();
+        let (idx, version) = split(id);
+        while self.meta.len() <= idx as usize {
+            self.free.push(self.meta.len() as u32);
+            self.meta.push(Meta { version:1, alive:false });
+        }
+        if self.meta[idx as usize].alive {
+            self.len -= 1;
+        } else if self.free.last() == Some(&idx) {
+            self.free.pop();
+        } else {
+            self.free.retain(|free| *free != idx);
+        }
+        let meta = &mut self.meta[idx as usize];
+        meta.version = version;
+        meta.alive = true;
+        self.len += 1;
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+    }
+
+    pub(crate) fn free(&mut self, id:EntityId) -> bool {
+        self.flush();
+        if !self.contains(id) {
+            return false;
+        }
+        let (idx, _) = split(id);
+        let meta = &mut self.meta[idx as usize];
+        meta.alive = false;
+        meta.version = meta.version.wrapping_add(2);
+        self.free.push(idx);
+        self.len -= 1;
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+        true
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.flush();
+        for idx in 1..self.meta.len() {
+            let meta = &mut self.meta[idx];
+            if meta.alive {
+                meta.alive = false;
+                meta.version = meta.version.wrapping_add(2);
+                self.free.push(idx as u32);
+            }
+        }
+        self.len = 0;
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+    }
+
+    /// Restores the reservation cursor after deserializing.
+    pub(crate) fn reset_cursor(&mut self) {
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+    }
+
+    pub(crate) fn iter<'a>(&'a self, disabled:Option<&'a SecondaryMap<EntityId, ()>>) -> EntityIter<'a> {
+        EntityIter {
+            meta:self.meta.iter().enumerate(),
+            disabled
+        }
+    }
+}
+
 pub struct EntityIter<'a> {
-    pub(crate) keys:Keys<'a, EntityId, ()>
+    meta:Enumerate<Iter<'a, Meta>>,
+    disabled:Option<&'a SecondaryMap<EntityId, ()>>
 }
 
 impl<'a> Iterator for EntityIter<'a> {
     type Item = EntityId;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.keys.next()
+        for (idx, meta) in self.meta.by_ref() {
+            if !meta.alive {
+                continue;
+            }
+            let id = id(idx as u32, meta.version);
+            match self.disabled {
+                Some(disabled) if disabled.contains_key(id) => continue,
+                _ => return Some(id)
+            }
+        }
+        None
     }
-}
\ No newline at end of file
+}
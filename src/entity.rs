@@ -1,5 +1,6 @@
 use std::cell::{Ref, RefMut};
-use crate::{EntityId, Registry, Component};
+use uuid::Uuid;
+use crate::{AttachError, EntityId, Registry, Component, Relation};
 
 pub struct EntityMut<'a> {
     id:EntityId,
@@ -22,18 +23,55 @@ impl<'a> EntityMut<'a> {
         self
     }
 
+    pub fn try_attach<T:Component>(&mut self, component:T) -> Result<&mut Self, AttachError> {
+        self.registry.component_try_attach(self.id, component)?;
+        Ok(self)
+    }
+
     pub fn detach<T:Component>(&mut self) -> &mut Self {
         self.registry.component_detach::<T>(self.id);
         self
     }
 
-    pub fn get<T:Component>(&self) -> Option<Ref<T>> {
+    pub fn disable(&mut self) -> &mut Self {
+        self.registry.disable(self.id);
+        self
+    }
+
+    pub fn enable(&mut self) -> &mut Self {
+        self.registry.enable(self.id);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.registry.is_enabled(self.id)
+    }
+
+    pub fn relate<R:Relation>(&mut self, target:EntityId) -> &mut Self {
+        self.registry.relate::<R>(self.id, target);
+        self
+    }
+
+    pub fn unrelate<R:Relation>(&mut self, target:EntityId) -> &mut Self {
+        self.registry.unrelate::<R>(self.id, target);
+        self
+    }
+
+    pub fn targets<R:Relation>(&self) -> &[EntityId] {
+        self.registry.targets::<R>(self.id)
+    }
+
+    pub fn get<T:Component>(&self) -> Option<Ref<'_, T>> {
         self.registry.component::<T>(self.id)
     }
 
-    pub fn get_mut<T:Component>(&self) -> Option<RefMut<T>> {
+    pub fn get_mut<T:Component>(&self) -> Option<RefMut<'_, T>> {
         self.registry.component_mut::<T>(self.id)
     }
+
+    pub fn component_uuids(&self) -> Vec<Uuid> {
+        self.registry.component_uuids(self.id)
+    }
 }
 
 pub struct Entity<'a> {
@@ -52,6 +90,18 @@ impl<'a> Entity<'a> {
         self.id
     }
 
+    pub fn is_enabled(&self) -> bool {
+        self.registry.is_enabled(self.id)
+    }
+
+    pub fn targets<R:Relation>(&self) -> &'a [EntityId] {
+        self.registry.targets::<R>(self.id)
+    }
+
+    pub fn sources<R:Relation>(&self) -> &'a [EntityId] {
+        self.registry.sources::<R>(self.id)
+    }
+
     pub fn get<T:Component>(&'a self) -> Option<Ref<'a, T>> {
         self.registry.component::<T>(self.id)
     }
@@ -59,4 +109,8 @@ impl<'a> Entity<'a> {
     pub fn get_mut<T:Component>(&'a self) -> Option<RefMut<'a, T>> {
         self.registry.component_mut::<T>(self.id)
     }
+
+    pub fn component_uuids(&self) -> Vec<Uuid> {
+        self.registry.component_uuids(self.id)
+    }
 }
\ No newline at end of file
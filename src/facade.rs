@@ -1,4 +1,4 @@
-use crate::{Registry, EntityId, EntityIter};
+use crate::{Registry, EntityId, EntityIter, Component, SortedIter};
 
 pub trait Facade<'a> where Self:Sized {
     fn new(registry:&'a Registry) -> Self;
@@ -9,6 +9,19 @@ pub trait Facade<'a> where Self:Sized {
             facade:self,
         }
     }
+    fn query_all<EF:EntityFacade<'a, Facade = Self>>(&'a self) -> EntityFacadeIter<'a, EF> {
+        EntityFacadeIter {
+            entities:self.registry().iter_all(),
+            facade:self,
+        }
+    }
+    /// Like `query` but visits entities holding `T` in the order set by `Registry::sort`.
+    fn query_sorted<EF:EntityFacade<'a, Facade = Self>, T:Component>(&'a self) -> EntityFacadeIter<'a, EF, SortedIter<'a>> {
+        EntityFacadeIter {
+            entities:self.registry().iter_sorted::<T>(),
+            facade:self,
+        }
+    }
 }
 
 pub trait EntityFacade<'a> where Self:Sized  {
@@ -16,17 +29,17 @@ pub trait EntityFacade<'a> where Self:Sized  {
     fn query(facade:&'a Self::Facade, id:EntityId) -> Option<Self>;
 }
 
-pub struct EntityFacadeIter<'a, EF:EntityFacade<'a>> {
-    entities:EntityIter<'a>,
+pub struct EntityFacadeIter<'a, EF:EntityFacade<'a>, I:Iterator<Item = EntityId> = EntityIter<'a>> {
+    entities:I,
     facade:&'a EF::Facade
 }
-impl<'a, EF:EntityFacade<'a>> Iterator for EntityFacadeIter<'a, EF> {
+impl<'a, EF:EntityFacade<'a>, I:Iterator<Item = EntityId>> Iterator for EntityFacadeIter<'a, EF, I> {
     type Item = EF;
 
     #[inline(always)]
     fn next(&mut self) -> Option<Self::Item> {
         for id in self.entities.by_ref() {
-            if let Some(q) = EF::query(&self.facade, id) {
+            if let Some(q) = EF::query(self.facade, id) {
                 return Some(q);
             }
         }
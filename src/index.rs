@@ -0,0 +1,193 @@
+use std::{any::Any, collections::BTreeMap, marker::PhantomData, ops::RangeBounds, rc::Rc};
+use slotmap::SecondaryMap;
+use uuid::Uuid;
+use crate::{Component, EntityId, Storage};
+
+pub struct IndexHandle<T, K> {
+    pub(crate) index:usize,
+    marker:PhantomData<(T, K)>
+}
+
+impl<T, K> IndexHandle<T, K> {
+    pub(crate) fn new(index:usize) -> Self {
+        Self {
+            index,
+            marker:PhantomData
+        }
+    }
+}
+
+impl<T, K> Clone for IndexHandle<T, K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, K> Copy for IndexHandle<T, K> {
+}
+
+pub struct Index<T, K> {
+    key_fn:Rc<dyn Fn(&T) -> K>,
+    unique:bool,
+    by_key:BTreeMap<K, Vec<EntityId>>,
+    by_entity:SecondaryMap<EntityId, K>
+}
+
+impl<T:Component, K:Ord + Clone + 'static> Index<T, K> {
+    pub(crate) fn new<F:Fn(&T) -> K + 'static>(key_fn:F, unique:bool) -> Self {
+        Self {
+            key_fn:Rc::new(key_fn),
+            unique,
+            by_key:BTreeMap::new(),
+            by_entity:SecondaryMap::new()
+        }
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    pub fn get(&self, key:&K) -> &[EntityId] {
+        match self.by_key.get(key) {
+            Some(ids) => ids,
+            None => &[]
+        }
+    }
+
+    pub fn first(&self, key:&K) -> Option<EntityId> {
+        self.get(key).first().copied()
+    }
+
+    pub fn range<R:RangeBounds<K>>(&self, range:R) -> impl Iterator<Item = (&K, EntityId)> {
+        self.by_key.range(range).flat_map(|(key, ids)| ids.iter().map(move |id| (key, *id)))
+    }
+
+    pub fn key(&self, id:EntityId) -> Option<&K> {
+        self.by_entity.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_entity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_entity.is_empty()
+    }
+
+    fn insert_key(&mut self, id:EntityId, key:K) {
+        self.remove_entity(id);
+        self.by_key.entry(key.clone()).or_default().push(id);
+        self.by_entity.insert(id, key);
+    }
+
+    fn remove_entity(&mut self, id:EntityId) {
+        if let Some(key) = self.by_entity.remove(id) {
+            if let Some(ids) = self.by_key.get_mut(&key) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.by_key.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) trait ComponentIndex {
+    fn component(&self) -> Uuid;
+    fn conflicts(&self, id:EntityId, component:&dyn Any) -> bool;
+    fn holder(&self, component:&dyn Any) -> Option<EntityId>;
+    fn insert(&mut self, id:EntityId, component:&dyn Any);
+    /// Re-reads the key of `id` from `storage`, returns false if the component is borrowed.
+    fn refresh(&mut self, storage:&Storage, id:EntityId) -> bool;
+    fn remove(&mut self, id:EntityId);
+    fn unique(&self) -> bool;
+    /// Entity other than `except` holding the key of the component `storage` holds for `id`,
+    /// `None` unless the index is unique.
+    fn holder_of(&self, storage:&Storage, id:EntityId, except:EntityId) -> Option<EntityId>;
+    fn rebuild(&mut self, storage:&Storage);
+    fn clone_box(&self) -> Box<dyn ComponentIndex>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T:Component, K:Ord + Clone + 'static> ComponentIndex for Index<T, K> {
+    fn component(&self) -> Uuid {
+        T::type_id()
+    }
+
+    fn conflicts(&self, id:EntityId, component:&dyn Any) -> bool {
+        if !self.unique {
+            return false;
+        }
+        match component.downcast_ref::<T>() {
+            Some(component) => self.get(&(self.key_fn)(component)).iter().any(|other| *other != id),
+            None => false
+        }
+    }
+
+    fn holder(&self, component:&dyn Any) -> Option<EntityId> {
+        let component = component.downcast_ref::<T>()?;
+        self.first(&(self.key_fn)(component))
+    }
+
+    fn insert(&mut self, id:EntityId, component:&dyn Any) {
+        if let Some(component) = component.downcast_ref::<T>() {
+            let key = (self.key_fn)(component);
+            self.insert_key(id, key);
+        }
+    }
+
+    fn refresh(&mut self, storage:&Storage, id:EntityId) -> bool {
+        let key = match storage.typed::<T>().get(id) {
+            Some(cell) => match cell.try_borrow() {
+                Ok(component) => Some((self.key_fn)(&component)),
+                Err(_) => return false
+            },
+            None => None
+        };
+        match key {
+            Some(key) => self.insert_key(id, key),
+            None => self.remove_entity(id)
+        }
+        true
+    }
+
+    fn remove(&mut self, id:EntityId) {
+        self.remove_entity(id);
+    }
+
+    fn unique(&self) -> bool {
+        self.unique
+    }
+
+    fn holder_of(&self, storage:&Storage, id:EntityId, except:EntityId) -> Option<EntityId> {
+        if !self.unique {
+            return None;
+        }
+        let key = (self.key_fn)(&*storage.typed::<T>().get(id)?.try_borrow().ok()?);
+        self.get(&key).iter().copied().find(|other| *other != except)
+    }
+
+    fn rebuild(&mut self, storage:&Storage) {
+        self.by_key.clear();
+        self.by_entity.clear();
+        for (id, cell) in storage.typed::<T>().iter() {
+            if let Ok(component) = cell.try_borrow() {
+                let key = (self.key_fn)(&component);
+                self.insert_key(id, key);
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn ComponentIndex> {
+        Box::new(Self {
+            key_fn:self.key_fn.clone(),
+            unique:self.unique,
+            by_key:self.by_key.clone(),
+            by_entity:self.by_entity.clone()
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Read, Write, ErrorKind};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{EntityId, invalid, bincode_error};
+
+const MAGIC:&[u8; 4] = b"REGJ";
+const VERSION:u8 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum Record {
+    Spawn(EntityId),
+    Despawn(EntityId),
+    Disable(EntityId),
+    Enable(EntityId),
+    Clear,
+    Set(EntityId, Uuid, Vec<u8>),
+    Remove(EntityId, Uuid),
+    Relate(Uuid, EntityId, EntityId),
+    Unrelate(Uuid, EntityId, EntityId),
+    Singleton(Uuid, Vec<u8>),
+    RemoveSingleton(Uuid)
+}
+
+/// Changes recorded by the registry since the last journal write, component changes are
+/// kept in the storages and only serialized when the journal is written.
+#[derive(Default)]
+pub(crate) struct JournalLog {
+    pub(crate) generation:u64,
+    pub(crate) records:Vec<Record>
+}
+
+/// Append-only log of registry changes, created by `Registry::start_journal` and written by
+/// `Registry::write_journal`. Every record carries its length and a checksum so a record torn
+/// by a crash is detected and ignored by `Registry::recover`.
+pub struct Journal<W:Write> {
+    writer:W,
+    generation:u64,
+    records:usize,
+    bytes:u64
+}
+
+impl<W:Write> Journal<W> {
+    pub(crate) fn new(mut writer:W, generation:u64) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&generation.to_le_bytes())?;
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            generation,
+            records:0,
+            bytes:13
+        })
+    }
+
+    /// Snapshot generation the journal applies to, bumped by `Registry::compact`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of records written so far.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Size of the journal in bytes, a good measure for when to compact.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub(crate) fn append(&mut self, record:&Record) -> io::Result<()> {
+        let payload = bincode::serialize(record).map_err(bincode_error)?;
+        let mut bytes = Vec::with_capacity(payload.len() + 8);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        self.writer.write_all(&bytes)?;
+        self.records += 1;
+        self.bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the journal header, returns `None` if the header itself was torn.
+pub(crate) fn read_journal_header(reader:&mut dyn Read) -> io::Result<Option<u64>> {
+    let mut header = [0; 13];
+    if !read_full(reader, &mut header)? {
+        return Ok(None);
+    }
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a registry journal"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid(format!("unsupported registry journal version {}", header[4])));
+    }
+    Ok(Some(u64::from_le_bytes(header[5..].try_into().unwrap())))
+}
+
+/// Reads the next record, returns `None` at the end of the journal or at a torn record.
+pub(crate) fn read_record(reader:&mut dyn Read) -> io::Result<Option<Record>> {
+    let mut header = [0; 8];
+    if !read_full(reader, &mut header)? {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let mut payload = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut payload)?;
+    if payload.len() != len as usize || crc32fast::hash(&payload) != checksum {
+        return Ok(None);
+    }
+    bincode::deserialize(&payload).map(Some).map_err(bincode_error)
+}
+
+/// Like `read_exact` but returns false instead of failing when the reader ends early.
+fn read_full(reader:&mut dyn Read, buf:&mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err)
+    }
+}
@@ -14,4 +14,49 @@ mod entities;
 mod commands;
 pub use commands::*;
 pub use entities::*;
-pub use uuid;
\ No newline at end of file
+mod reflect;
+pub use reflect::*;
+mod dynamic;
+pub use dynamic::*;
+mod dump;
+pub use dump::*;
+mod name;
+pub use name::*;
+mod index;
+pub use index::*;
+mod spatial;
+pub use spatial::*;
+mod relation;
+pub use relation::*;
+mod replication;
+pub use replication::*;
+mod stats;
+pub use stats::*;
+mod required;
+pub(crate) use required::*;
+mod stream;
+pub(crate) use stream::*;
+mod codec;
+pub use codec::*;
+mod journal;
+pub use journal::*;
+mod query;
+pub use query::*;
+mod universe;
+pub use universe::*;
+mod timers;
+pub use timers::*;
+#[cfg(feature = "scripting")]
+mod scripting;
+#[cfg(feature = "scripting")]
+pub use scripting::*;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+pub use uuid;
+pub use serde_json;
+#[cfg(feature = "scripting")]
+pub use rhai;
+#[cfg(feature = "sqlite")]
+pub use rusqlite;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::fmt::Display;
+use fxhash::FxHashMap;
+use slotmap::SecondaryMap;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Component, EntityId, Storage};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new(name:&str) -> Self {
+        Self(name.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Name {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Component for Name {
+    fn type_id() -> Uuid {
+        uuid::uuid!("5b0a4a36-3c1f-4f7e-9a55-0e5e3d1a7c11")
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub EntityId);
+
+impl Component for Parent {
+    fn type_id() -> Uuid {
+        uuid::uuid!("0c7b6f1e-92d4-4b6a-8f3e-6d2c9a41b5e8")
+    }
+}
+
+/// Name to entity lookup, kept up to date through the touched entries of the `Name` storage.
+/// Entries are validated against the `Name` component on read, so changes still pending are harmless.
+#[derive(Default, Clone)]
+pub(crate) struct NameIndex {
+    pub(crate) by_name:FxHashMap<String, Vec<EntityId>>,
+    pub(crate) by_id:SecondaryMap<EntityId, String>,
+    pub(crate) dirty:bool,
+    pub(crate) unique:bool
+}
+
+impl NameIndex {
+    pub(crate) fn insert(&mut self, name:&str, id:EntityId) {
+        self.remove(id);
+        self.by_name.entry(name.to_string()).or_default().push(id);
+        self.by_id.insert(id, name.to_string());
+    }
+
+    pub(crate) fn remove(&mut self, id:EntityId) {
+        let Some(name) = self.by_id.remove(id) else {
+            return;
+        };
+        if let Some(ids) = self.by_name.get_mut(&name) {
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                self.by_name.remove(&name);
+            }
+        }
+    }
+
+    /// Re-reads the name of `id` from `storage`, returns false if the component is borrowed.
+    pub(crate) fn refresh(&mut self, storage:&Storage, id:EntityId) -> bool {
+        match storage.typed::<Name>().get(id).map(|name| name.try_borrow()) {
+            Some(Ok(name)) => self.insert(name.as_str(), id),
+            Some(Err(_)) => return false,
+            None => self.remove(id)
+        }
+        true
+    }
+
+    pub(crate) fn get(&self, name:&str) -> &[EntityId] {
+        match self.by_name.get(name) {
+            Some(ids) => ids,
+            None => &[]
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.by_name.clear();
+        self.by_id.clear();
+        self.dirty = false;
+    }
+}
@@ -0,0 +1,562 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::{EntityId, Registry};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    Syntax { position:usize, message:String },
+    UnknownComponent(String)
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f:&mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Syntax { position, message } => write!(f, "syntax error at {}: {}", position, message),
+            QueryError::UnknownComponent(name) => write!(f, "component {} not registered", name)
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f64),
+    Index(usize),
+    Str(String),
+    Op(Op),
+    Dot,
+    Comma,
+    Open,
+    Close
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+impl Op {
+    fn test(self, ordering:Option<Ordering>) -> bool {
+        match self {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+    }
+}
+
+fn syntax<T>(position:usize, message:impl Into<String>) -> Result<T, QueryError> {
+    Err(QueryError::Syntax { position, message:message.into() })
+}
+
+fn is_word(c:char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(text:&str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars:Vec<char> = text.chars().collect();
+    let mut tokens:Vec<(usize, Token)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let after_dot = matches!(tokens.last(), Some((_, Token::Dot)));
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            },
+            '.' => {
+                i += 1;
+                Token::Dot
+            },
+            ',' => {
+                i += 1;
+                Token::Comma
+            },
+            '(' => {
+                i += 1;
+                Token::Open
+            },
+            ')' => {
+                i += 1;
+                Token::Close
+            },
+            '=' | '!' | '<' | '>' => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (c, next) {
+                    ('=', Some('=')) => (Op::Eq, 2),
+                    ('=', _) => (Op::Eq, 1),
+                    ('!', Some('=')) => (Op::Ne, 2),
+                    ('<', Some('>')) => (Op::Ne, 2),
+                    ('<', Some('=')) => (Op::Le, 2),
+                    ('<', _) => (Op::Lt, 1),
+                    ('>', Some('=')) => (Op::Ge, 2),
+                    ('>', _) => (Op::Gt, 1),
+                    _ => return syntax(start, "expected `!=`")
+                };
+                i += len;
+                Token::Op(op)
+            },
+            '\'' | '"' => {
+                let mut string = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return syntax(start, "unterminated string"),
+                        Some('\\') if i + 1 < chars.len() => {
+                            string.push(chars[i + 1]);
+                            i += 2;
+                        },
+                        Some(end) if *end == c => {
+                            i += 1;
+                            break;
+                        },
+                        Some(other) => {
+                            string.push(*other);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Str(string)
+            },
+            c if is_word(c) || c == '-' => {
+                // uuids may start with a digit, so they are checked before numbers
+                let uuid:String = chars[i..].iter().take(36).collect();
+                if uuid.len() == 36 && Uuid::parse_str(&uuid).is_ok() && !chars.get(i + 36).is_some_and(|c| is_word(*c)) {
+                    i += 36;
+                    Token::Word(uuid)
+                } else if c.is_ascii_digit() && after_dot {
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                    let index:String = chars[start..i].iter().collect();
+                    Token::Index(index.parse().map_err(|_| QueryError::Syntax { position:start, message:"invalid index".to_string() })?)
+                } else if c.is_ascii_digit() || c == '-' {
+                    i += 1;
+                    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E' || (matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E'))) {
+                        i += 1;
+                    }
+                    let number:String = chars[start..i].iter().collect();
+                    match number.parse() {
+                        Ok(number) => Token::Number(number),
+                        Err(_) => return syntax(start, format!("invalid number `{}`", number))
+                    }
+                } else {
+                    while i < chars.len() {
+                        if is_word(chars[i]) {
+                            i += 1;
+                        } else if chars[i] == ':' && chars.get(i + 1) == Some(&':') && chars.get(i + 2).is_some_and(|c| is_word(*c)) {
+                            i += 2;
+                        } else {
+                            break;
+                        }
+                    }
+                    Token::Word(chars[start..i].iter().collect())
+                }
+            },
+            c => return syntax(start, format!("unexpected `{}`", c))
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Key(String),
+    Index(usize)
+}
+
+/// A component, optionally followed by fields of its reflected value, like `Position.x`.
+#[derive(Debug, Clone, PartialEq)]
+struct Path {
+    component:String,
+    fields:Vec<Field>
+}
+
+impl Display for Path {
+    fn fmt(&self, f:&mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.component)?;
+        for field in self.fields.iter() {
+            match field {
+                Field::Key(key) => write!(f, ".{}", key)?,
+                Field::Index(index) => write!(f, ".{}", index)?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Path(Path),
+    Literal(Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Op, Box<Expr>, Box<Expr>)
+}
+
+const KEYWORDS:[&str; 11] = ["where", "order", "by", "limit", "offset", "and", "or", "not", "asc", "desc", "null"];
+
+struct Parser {
+    tokens:Vec<(usize, Token)>,
+    next:usize,
+    end:usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map(|(position, _)| *position).unwrap_or(self.end)
+    }
+
+    fn keyword(&mut self, keyword:&str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn token(&mut self, token:Token) -> bool {
+        if self.peek() == Some(&token) {
+            self.next += 1;
+            return true;
+        }
+        false
+    }
+
+    fn path(&mut self) -> Result<Path, QueryError> {
+        let component = match self.peek() {
+            Some(Token::Word(word)) if !KEYWORDS.iter().any(|keyword| word.eq_ignore_ascii_case(keyword)) => word.clone(),
+            _ => return syntax(self.position(), "expected a component")
+        };
+        self.next += 1;
+        let mut fields = Vec::new();
+        while self.token(Token::Dot) {
+            let field = match self.peek() {
+                Some(Token::Word(word)) => Field::Key(word.clone()),
+                Some(Token::Index(index)) => Field::Index(*index),
+                _ => return syntax(self.position(), "expected a field")
+            };
+            fields.push(field);
+            self.next += 1;
+        }
+        Ok(Path { component, fields })
+    }
+
+    fn limit(&mut self) -> Result<usize, QueryError> {
+        match self.peek() {
+            Some(Token::Number(number)) if number.fract() == 0.0 && *number >= 0.0 => {
+                let number = *number as usize;
+                self.next += 1;
+                Ok(number)
+            },
+            _ => syntax(self.position(), "expected a count")
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, QueryError> {
+        let left = self.operand()?;
+        if let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            self.next += 1;
+            return Ok(Expr::Compare(op, Box::new(left), Box::new(self.operand()?)));
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Expr, QueryError> {
+        let literal = match self.peek() {
+            Some(Token::Number(number)) => serde_json::Number::from_f64(*number).map(Value::Number).unwrap_or_default(),
+            Some(Token::Str(string)) => Value::String(string.clone()),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("true") => Value::Bool(true),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("false") => Value::Bool(false),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("null") => Value::Null,
+            Some(Token::Open) => {
+                self.next += 1;
+                let expr = self.or()?;
+                if !self.token(Token::Close) {
+                    return syntax(self.position(), "expected `)`");
+                }
+                return Ok(expr);
+            },
+            _ => return Ok(Expr::Path(self.path()?))
+        };
+        self.next += 1;
+        Ok(Expr::Literal(literal))
+    }
+}
+
+/// A query like `Position, Health where Health.amount < 10 order by Position.x desc limit 20`.
+/// Entities need every selected component, fields of the reflected components are addressed with `.`
+/// and components by type name or UUID, built-in ones are named like `registry::Name`. Conditions
+/// support comparisons, `and`, `or`, `not` and bare components which test for presence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    select:Vec<Path>,
+    filter:Option<Expr>,
+    order:Vec<(Path, bool)>,
+    limit:Option<usize>,
+    offset:usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRow {
+    pub id:EntityId,
+    pub values:Vec<Value>
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryResult {
+    pub columns:Vec<String>,
+    pub rows:Vec<QueryRow>
+}
+
+impl Display for QueryResult {
+    fn fmt(&self, f:&mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "id | {}", self.columns.join(" | "))?;
+        for row in self.rows.iter() {
+            let values:Vec<String> = row.values.iter().map(|value| value.to_string()).collect();
+            writeln!(f, "{:?} | {}", row.id, values.join(" | "))?;
+        }
+        write!(f, "({} rows)", self.rows.len())
+    }
+}
+
+fn field<'a>(mut value:&'a Value, fields:&[Field]) -> &'a Value {
+    for field in fields {
+        let next = match (field, value) {
+            (Field::Key(key), Value::Object(object)) => object.get(key),
+            (Field::Index(index), Value::Array(array)) => array.get(*index),
+            (Field::Index(index), Value::Object(object)) => object.get(&index.to_string()),
+            _ => None
+        };
+        value = next.unwrap_or(&Value::Null);
+    }
+    value
+}
+
+fn compare(a:&Value, b:&Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None
+    }
+}
+
+/// Total order used by `order by`, values of different kinds are ordered by kind.
+fn sort_order(a:&Value, b:&Value) -> Ordering {
+    fn rank(value:&Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5
+        }
+    }
+    rank(a).cmp(&rank(b)).then_with(|| compare(a, b).unwrap_or(Ordering::Equal))
+}
+
+fn truthy(value:&Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(bool) => *bool,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(string) => !string.is_empty(),
+        _ => true
+    }
+}
+
+/// Reflected components of the entity being evaluated, in the order of `Query::components`.
+struct Row<'a> {
+    components:&'a [(String, Uuid)],
+    values:Vec<Option<Value>>
+}
+
+impl Row<'_> {
+    fn component(&self, name:&str) -> Option<&Value> {
+        let slot = self.components.iter().position(|(component, _)| component == name)?;
+        self.values[slot].as_ref()
+    }
+
+    fn value(&self, path:&Path) -> Value {
+        match self.component(&path.component) {
+            Some(value) => field(value, &path.fields).clone(),
+            None => Value::Null
+        }
+    }
+
+    fn eval(&self, expr:&Expr) -> Value {
+        match expr {
+            Expr::Path(path) => self.value(path),
+            Expr::Literal(value) => value.clone(),
+            Expr::Compare(op, a, b) => Value::Bool(op.test(compare(&self.eval(a), &self.eval(b)))),
+            expr => Value::Bool(self.test(expr))
+        }
+    }
+
+    fn test(&self, expr:&Expr) -> bool {
+        match expr {
+            Expr::Path(path) if path.fields.is_empty() => self.component(&path.component).is_some(),
+            Expr::Not(expr) => !self.test(expr),
+            Expr::And(a, b) => self.test(a) && self.test(b),
+            Expr::Or(a, b) => self.test(a) || self.test(b),
+            expr => truthy(&self.eval(expr))
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(text:&str) -> Result<Self, QueryError> {
+        let mut parser = Parser {
+            tokens:tokenize(text)?,
+            next:0,
+            end:text.len()
+        };
+        let mut select = vec![parser.path()?];
+        while parser.token(Token::Comma) {
+            select.push(parser.path()?);
+        }
+        let filter = if parser.keyword("where") { Some(parser.or()?) } else { None };
+        let mut order = Vec::new();
+        if parser.keyword("order") {
+            if !parser.keyword("by") {
+                return syntax(parser.position(), "expected `by`");
+            }
+            loop {
+                let path = parser.path()?;
+                let descending = parser.keyword("desc");
+                if !descending {
+                    parser.keyword("asc");
+                }
+                order.push((path, descending));
+                if !parser.token(Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let limit = if parser.keyword("limit") { Some(parser.limit()?) } else { None };
+        let offset = if parser.keyword("offset") { parser.limit()? } else { 0 };
+        if parser.peek().is_some() {
+            return syntax(parser.position(), "unexpected input");
+        }
+        Ok(Self { select, filter, order, limit, offset })
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        fn walk<'a>(expr:&'a Expr, paths:&mut Vec<&'a Path>) {
+            match expr {
+                Expr::Path(path) => paths.push(path),
+                Expr::Literal(_) => (),
+                Expr::Not(expr) => walk(expr, paths),
+                Expr::And(a, b) | Expr::Or(a, b) | Expr::Compare(_, a, b) => {
+                    walk(a, paths);
+                    walk(b, paths);
+                }
+            }
+        }
+        let mut paths:Vec<&Path> = self.select.iter().chain(self.order.iter().map(|(path, _)| path)).collect();
+        if let Some(filter) = &self.filter {
+            walk(filter, &mut paths);
+        }
+        paths.into_iter()
+    }
+
+    /// Components referenced by the query, resolved against `registry`.
+    fn components(&self, registry:&Registry) -> Result<Vec<(String, Uuid)>, QueryError> {
+        let mut components:Vec<(String, Uuid)> = Vec::new();
+        for path in self.paths() {
+            if components.iter().any(|(name, _)| *name == path.component) {
+                continue;
+            }
+            let uuid = registry.component_uuid(&path.component)
+                .or_else(|| Uuid::parse_str(&path.component).ok().filter(|uuid| registry.component_name(*uuid).is_some()))
+                .ok_or_else(|| QueryError::UnknownComponent(path.component.clone()))?;
+            components.push((path.component.clone(), uuid));
+        }
+        Ok(components)
+    }
+
+    pub fn run(&self, registry:&Registry) -> Result<QueryResult, QueryError> {
+        let components = self.components(registry)?;
+        let required:Vec<Uuid> = self.select.iter().map(|path| {
+            components.iter().find(|(name, _)| *name == path.component).map(|(_, uuid)| *uuid).unwrap_or_default()
+        }).collect();
+        let mut rows:Vec<(QueryRow, Vec<Value>)> = Vec::new();
+        for id in registry.iter_with(&required) {
+            let row = Row {
+                components:&components,
+                values:components.iter().map(|(_, uuid)| registry.component_dyn(id, *uuid)).collect()
+            };
+            if let Some(filter) = &self.filter {
+                if !row.test(filter) {
+                    continue;
+                }
+            }
+            let keys = self.order.iter().map(|(path, _)| row.value(path)).collect();
+            let values = self.select.iter().map(|path| row.value(path)).collect();
+            rows.push((QueryRow { id, values }, keys));
+        }
+        if !self.order.is_empty() {
+            rows.sort_by(|(a, a_keys), (b, b_keys)| {
+                let keys = a_keys.iter().zip(b_keys.iter()).zip(self.order.iter());
+                keys.map(|((a, b), (_, descending))| if *descending { sort_order(b, a) } else { sort_order(a, b) })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or_else(|| a.id.cmp(&b.id))
+            });
+        }
+        let rows = rows.into_iter().map(|(row, _)| row).skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect();
+        Ok(QueryResult {
+            columns:self.select.iter().map(|path| path.to_string()).collect(),
+            rows
+        })
+    }
+}
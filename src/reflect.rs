@@ -0,0 +1,28 @@
+use std::fmt::Display;
+use uuid::Uuid;
+use crate::{AttachError, EntityId};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectError {
+    UnknownComponent(Uuid),
+    UnknownEntity(EntityId),
+    Borrowed,
+    Invalid(String),
+    /// The value takes a unique key or name held by another entity, the component was left as it was.
+    Conflict(AttachError)
+}
+
+impl Display for ReflectError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReflectError::UnknownComponent(uuid) => write!(f, "component {} not registered", uuid),
+            ReflectError::UnknownEntity(id) => write!(f, "entity {:?} does not exist", id),
+            ReflectError::Borrowed => write!(f, "component is already borrowed"),
+            ReflectError::Invalid(err) => write!(f, "invalid value: {}", err),
+            ReflectError::Conflict(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {
+}
@@ -1,129 +1,570 @@
-use std::{ cell::{RefCell, RefMut, Ref}, collections::HashMap, io::BufWriter, any::type_name, mem::replace};
-use fxhash::FxHashMap;
+use std::{ cell::{RefCell, RefMut, Ref}, cmp::Ordering, rc::Rc, collections::HashMap, io::{self, BufWriter, Read, Write}, any::type_name, mem::replace, fmt::{Debug, Display}, time::Duration};
+use fxhash::{FxHashMap, FxHashSet};
 use serde::{Serialize, Deserialize};
-use slotmap::{SlotMap};
+use slotmap::{SlotMap, SecondaryMap};
+use serde_json::Value;
 use uuid::Uuid;
-use crate::{Component, EntityId, Storage, EntityMut, Entity, Components, Facade, EntityIter, Commands};
+use crate::{Entities, EntitySlots, Relation, RelationStorage, ReflectError, Schema, Dump, Name, NameIndex, Parent, ComponentIndex, Index, IndexHandle, SpatialIndex, SpatialHandle, Component, EntityId, Storage, EntityMut, Entity, Components, Facade, EntityIter, Commands, Replicate, Required, reaches, Section, bincode_error, read_header, read_section, write_header, write_section, write_compressed, read_compressed, MAGIC, Codec, Journal, JournalLog, Record, read_journal_header, read_record, SerializableComponent, BorrowError, Conflicts, RegistryStats, StorageStats, Query, QueryResult, QueryError, SortedIter, Timers, TimerId, Delay, TimedCommand, Action, CommandError};
 
+/// Components removed from an entity by `take_entity`, keyed by component id.
+pub(crate) type TakenComponents = Vec<(Uuid, Box<dyn std::any::Any>)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachError {
+    DuplicateName { name:String, entity:String },
+    UniqueViolation { component:String, entity:String },
+    MissingRequired { component:String, required:String }
+}
+
+impl Display for AttachError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachError::DuplicateName { name, entity } => write!(f, "name '{}' already used by {}", name, entity),
+            AttachError::UniqueViolation { component, entity } => write!(f, "unique index on {} already holds the key for {}", component, entity),
+            AttachError::MissingRequired { component, required } => write!(f, "{} requires {}", component, required)
+        }
+    }
+}
+
+impl std::error::Error for AttachError {
+}
+
+/// Layout written by `serialize` without a codec. Later additions go in the parts appended
+/// after it, older readers ignore those and older data loads without them.
 #[derive(Serialize, Deserialize)]
 struct SerializableRegistry {
-    entities:SlotMap<EntityId, ()>,
+    entities:EntitySlots,
     serialized_components:HashMap<Uuid, Vec<u8>>,
     serialized_singletons:HashMap<Uuid, Vec<u8>>
 }
 
+#[derive(Default, Serialize, Deserialize)]
+struct SerializableAdditions {
+    disabled:SecondaryMap<EntityId, ()>,
+    serialized_relations:HashMap<Uuid, Vec<u8>>
+}
+
 pub struct Registry {
     commands:RefCell<Commands>,
-    entities:SlotMap<EntityId, ()>,
+    entities:Entities,
+    disabled:SecondaryMap<EntityId, ()>,
     singleton:EntityId,
     components:FxHashMap<Uuid, Storage>,
     singletons:FxHashMap<Uuid, Storage>,
+    reset_singletons:FxHashSet<Uuid>,
+    relations:FxHashMap<Uuid, RelationStorage>,
+    names:RefCell<NameIndex>,
+    indexes:RefCell<Vec<Box<dyn ComponentIndex>>>,
+    strict_borrows:bool,
+    required:FxHashMap<Uuid, Vec<Required>>,
+    strict_required:bool,
+    codec:Codec,
+    journal:Option<JournalLog>,
+    timers:RefCell<Timers>,
+    shared:bool
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Registry {
     pub fn new() -> Self {
-        let entities = SlotMap::default();
+        let entities = Entities::default();
         let components = FxHashMap::default();
         let singletons = FxHashMap::default();
+        let relations = FxHashMap::default();
         let singleton = SlotMap::<EntityId, ()>::default().insert(());
-        Self {
+        let mut registry = Self {
             entities,
+            disabled:SecondaryMap::new(),
             components,
             singletons,
+            reset_singletons:FxHashSet::default(),
+            relations,
             singleton,
-            commands:RefCell::new(Commands::default())
-        }
+            commands:RefCell::new(Commands::default()),
+            names:RefCell::new(NameIndex::default()),
+            indexes:RefCell::new(Vec::new()),
+            strict_borrows:false,
+            required:FxHashMap::default(),
+            strict_required:false,
+            codec:Codec::None,
+            journal:None,
+            timers:RefCell::new(Timers::default()),
+            shared:false
+        };
+        registry.register_builtin::<Name>("registry::Name");
+        registry.register_builtin::<Parent>("registry::Parent");
+        registry.component_storage_mut::<Name>().tracked = true;
+        registry
     }
 
-    pub fn push<F:Fn(&mut Self)->() + 'static>(&self, f:F) {
+    /// Built-in components are named with the crate prefix so they do not collide with user types.
+    fn register_builtin<T:SerializableComponent + Debug>(&mut self, name:&str) {
+        let mut storage = Storage::new::<T>();
+        storage.name = name.to_string();
+        self.insert_storage(T::type_id(), storage);
+        self.register_debug::<T>();
+    }
+
+    pub fn push<F:Fn(&mut Self) + 'static>(&self, f:F) {
         self.commands.borrow_mut().push(Box::new(f));
     }
 
+    /// Queues `f` to run once `delay` has passed, the returned handle can cancel it.
+    /// Closures are not saved by `serialize`, use `push_delayed_command` for timers that survive loading.
+    pub fn push_delayed<D:Into<Delay>, F:Fn(&mut Self) + 'static>(&self, delay:D, f:F) -> TimerId {
+        self.timers.borrow_mut().push(delay.into(), Action::Closure(Rc::new(f)))
+    }
+
+    /// Queues a command registered with `register_command` to run once `delay` has passed.
+    pub fn push_delayed_command<D:Into<Delay>, C:TimedCommand>(&self, delay:D, command:C) -> TimerId {
+        let mut timers = self.timers.borrow_mut();
+        let action = timers.encode(&command);
+        timers.push(delay.into(), action)
+    }
+
+    /// Cancels a delayed command, returns false if it already ran or was cancelled.
+    pub fn cancel(&self, timer:TimerId) -> bool {
+        self.timers.borrow_mut().cancel(timer)
+    }
+
+    pub fn is_pending(&self, timer:TimerId) -> bool {
+        self.timers.borrow().contains(timer)
+    }
+
+    /// Number of delayed commands that have not run yet.
+    pub fn pending_timers(&self) -> usize {
+        self.timers.borrow().len()
+    }
+
+    pub fn register_command<C:TimedCommand>(&mut self) {
+        self.check_unshared();
+        self.timers.get_mut().register::<C>();
+    }
+
+    /// Moves the clock of `Delay::Duration` timers forward, they run on the next `execute`.
+    pub fn advance(&mut self, elapsed:Duration) {
+        self.timers.get_mut().advance(elapsed);
+    }
+
+    /// Number of `execute` calls so far, counted by `Delay::Ticks` timers.
+    pub fn tick(&self) -> u64 {
+        self.timers.borrow().tick()
+    }
+
+    /// Time passed to `advance` so far.
+    pub fn time(&self) -> Duration {
+        self.timers.borrow().time()
+    }
+
+    /// Runs the delayed commands that are due, then the queued commands. Timed commands whose
+    /// data fails to decode are dropped, use `try_execute` to see them.
     pub fn execute(&mut self) {
+        let _ = self.try_execute();
+    }
+
+    /// Like `execute` but returns the first timed command that failed to decode, after running everything else.
+    pub fn try_execute(&mut self) -> Result<(), CommandError> {
+        self.flush_entities();
+        let mut result = Ok(());
+        let due = self.timers.get_mut().due();
+        for action in due {
+            match action {
+                Action::Closure(f) => f(self),
+                Action::Data(command, bytes) => {
+                    if let Some(run) = self.timers.get_mut().command(command) {
+                        if let Err(error) = run(self, &bytes) {
+                            if result.is_ok() {
+                                result = Err(CommandError { command, error });
+                            }
+                        }
+                    }
+                }
+            }
+        }
         let commands = replace(&mut self.commands, RefCell::new(Commands::default()));
         commands.borrow_mut().execute(self);
+        result
+    }
+
+    /// Lists storages, singletons and entities with their components. Components are printed with
+    /// `Debug` if it was captured by `register_debug_component` or `register_debug`, as JSON otherwise.
+    pub fn dump(&self) -> Dump<'_> {
+        Dump::new(self)
     }
 
     pub fn facade<'a, T:Facade<'a>>(&'a self) -> T {
         T::new(self)
     }
 
-    pub fn register_singleton<T:Component + Default>(&mut self) {
+    /// Parses and runs a text query, see `Query`.
+    pub fn query(&self, query:&str) -> Result<QueryResult, QueryError> {
+        Query::parse(query)?.run(self)
+    }
+
+    /// Registers a singleton holding `T::default()`, the value is reset by `clear`.
+    pub fn register_singleton<T:SerializableComponent>(&mut self) {
         let id = T::type_id();
-        if self.singletons.get(&id).is_some() {
+        if self.singletons.contains_key(&id) {
             panic!("{} singleton already registered!", type_name::<T>());
         }
-        unsafe {
-            let mut storage = Storage::new::<T>();
-            let view = storage.get_mut::<T>();
-            view.insert(self.singleton, RefCell::new(T::default()));
+        self.add_singleton_storage(id, Storage::new::<T>);
+        self.reset_singletons.insert(id);
+        self.set_singleton(T::default());
+    }
+
+    /// Inserts or replaces a singleton, returning the previous value.
+    pub fn insert_singleton<T:SerializableComponent>(&mut self, value:T) -> Option<T> {
+        self.add_singleton_storage(T::type_id(), Storage::new::<T>);
+        self.set_singleton(value)
+    }
+
+    /// Inserts or replaces a singleton that is skipped by `serialize` and `clone`.
+    pub fn insert_runtime_singleton<T:Component>(&mut self, value:T) -> Option<T> {
+        self.add_singleton_storage(T::type_id(), Storage::new_runtime::<T>);
+        self.set_singleton(value)
+    }
+
+    fn add_singleton_storage(&mut self, id:Uuid, new:fn() -> Storage) {
+        if !self.singletons.contains_key(&id) {
+            let mut storage = new();
+            storage.journaled = self.journal.is_some() && storage.serialized;
             self.singletons.insert(id, storage);
         }
     }
 
-    pub fn singleton<T:Component>(&self) -> Option<Ref<T>> {
-        unsafe {
-            if let Some(cell) = self.singleton_storage::<T>().get::<T>().get(self.singleton) {
-                if let Ok(cell) = cell.try_borrow() {
-                    return Some(cell);
-                }
-            }
+    pub fn remove_singleton<T:Component>(&mut self) -> Option<T> {
+        let id = T::type_id();
+        self.reset_singletons.remove(&id);
+        let mut storage = self.singletons.remove(&id)?;
+        if storage.journaled {
+            self.log(Record::RemoveSingleton(id));
+        }
+        storage.typed_mut::<T>().remove(self.singleton).map(RefCell::into_inner)
+    }
 
-            None
+    pub fn has_singleton<T:Component>(&self) -> bool {
+        match self.singletons.get(&T::type_id()) {
+            Some(storage) => storage.has(self.singleton),
+            None => false
         }
     }
 
-    pub fn singleton_mut<T:Component>(&self) -> Option<RefMut<T>> {
-        unsafe {
-            if let Some(cell) = self.singleton_storage::<T>().get::<T>().get(self.singleton) {
-                if let Ok(cell) = cell.try_borrow_mut() {
-                    return Some(cell);
-                }
-            }
+    pub fn singleton_or_insert_with<T:SerializableComponent, F:FnOnce() -> T>(&mut self, f:F) -> RefMut<'_, T> {
+        if !self.has_singleton::<T>() {
+            self.insert_singleton(f());
+        }
+        self.singleton_mut::<T>().expect("singleton is borrowed")
+    }
+
+    /// Sets whether `clear` resets the singleton to its default value.
+    pub fn set_singleton_reset<T:SerializableComponent>(&mut self, reset:bool) {
+        let id = T::type_id();
+        if !self.singletons.contains_key(&id) {
+            panic!("{} singleton type not registered!", type_name::<T>());
+        }
+        if reset {
+            self.reset_singletons.insert(id);
+        } else {
+            self.reset_singletons.remove(&id);
+        }
+    }
 
-            None
+    fn set_singleton<T:Component>(&mut self, value:T) -> Option<T> {
+        let singleton = self.singleton;
+        let storage = self.singletons.get_mut(&T::type_id())?;
+        storage.record(singleton);
+        storage.typed_mut::<T>().insert(singleton, RefCell::new(value)).map(RefCell::into_inner)
+    }
+
+    pub fn singleton<T:Component>(&self) -> Option<Ref<'_, T>> {
+        let storage = self.singletons.get(&T::type_id())?;
+        storage.typed::<T>().get(self.singleton)?.try_borrow().ok()
+    }
+
+    pub fn singleton_mut<T:Component>(&self) -> Option<RefMut<'_, T>> {
+        let storage = self.singletons.get(&T::type_id())?;
+        let singleton = storage.typed::<T>().get(self.singleton)?.try_borrow_mut().ok()?;
+        storage.record(self.singleton);
+        Some(singleton)
+    }
+
+    pub fn iter(&self) -> EntityIter<'_> {
+        let disabled = if self.disabled.is_empty() { None } else { Some(&self.disabled) };
+        self.entities.iter(disabled)
+    }
+
+    pub fn iter_all(&self) -> EntityIter<'_> {
+        self.entities.iter(None)
+    }
+
+    /// Enabled entities holding `T`, in the order set by `sort` or in slot order if `T` was not sorted.
+    pub fn iter_sorted<T:Component>(&self) -> SortedIter<'_> {
+        let disabled = if self.disabled.is_empty() { None } else { Some(&self.disabled) };
+        SortedIter::new(self.component_storage::<T>(), self.entities.iter(None), disabled)
+    }
+
+    /// Orders the storage of `T` so component iterators, `iter_sorted` and `Facade::query_sorted` visit it
+    /// in `cmp` order until `T` is attached or detached. The order is sorted again in place, so re-sorting
+    /// a mostly sorted storage every frame is cheap and does not allocate.
+    pub fn sort<T:Component, F:FnMut(&T, &T) -> Ordering>(&mut self, mut cmp:F) {
+        let storage = self.component_storage_mut::<T>();
+        let (mut order, ordered) = storage.take_order();
+        let map = storage.typed::<T>();
+        if !ordered {
+            order.clear();
+            order.extend(map.keys());
         }
+        order.sort_by(|a, b| cmp(&map[*a].borrow(), &map[*b].borrow()));
+        storage.set_order(order);
     }
 
-    pub fn iter(&self) -> EntityIter {
-        EntityIter { keys: self.entities.keys() }
+    pub fn sort_by_key<T:Component, K:Ord, F:FnMut(&T) -> K>(&mut self, mut key:F) {
+        self.sort::<T, _>(|a, b| key(a).cmp(&key(b)));
     }
 
     pub fn len(&self) -> usize {
         self.entities.len()
     }
 
-    pub fn entity(&self, id:EntityId) -> Option<Entity> {
-        if self.entities.get(id).is_some() {
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, id:EntityId) -> bool {
+        self.entities.contains(id)
+    }
+
+    pub fn disable(&mut self, id:EntityId) {
+        if self.entities.contains(id) {
+            self.disabled.insert(id, ());
+            self.log(Record::Disable(id));
+        }
+    }
+
+    pub fn enable(&mut self, id:EntityId) {
+        if self.disabled.remove(id).is_some() {
+            self.log(Record::Enable(id));
+        }
+    }
+
+    pub fn is_enabled(&self, id:EntityId) -> bool {
+        self.entities.contains(id) && !self.disabled.contains_key(id)
+    }
+
+    pub fn entity(&self, id:EntityId) -> Option<Entity<'_>> {
+        if self.entities.contains(id) {
             return Some(Entity::new(id, self));
         }
         None
     } 
 
-    pub fn entity_mut(&mut self, id:EntityId) -> Option<EntityMut> {
-        if self.entities.get(id).is_some() {
+    pub fn entity_mut(&mut self, id:EntityId) -> Option<EntityMut<'_>> {
+        if self.entities.contains(id) {
             return Some(EntityMut::new(id, self));
         }
         None
     }
 
-    pub fn register_component<T:Component>(&mut self) {
+    /// Registers a saved component, `dump` prints it as JSON, see `register_debug_component`.
+    pub fn register_component<T:SerializableComponent>(&mut self) {
+        self.check_unshared();
         let id = T::type_id();
-        if self.components.get(&id).is_some() {
+        if self.components.contains_key(&id) {
             panic!("{} component already registered!", type_name::<T>());
         }
-        self.components.insert(id, Storage::new::<T>());
+        let mut storage = Storage::new::<T>();
+        storage.journaled = self.journal.is_some();
+        self.insert_storage(id, storage);
+        self.add_required::<T>();
+        self.check_required_defaults();
     }
 
-    unsafe fn singleton_storage<T:Component>(&self) -> &Storage {
+    /// Registers a component that is skipped by `serialize` and `clone`, see `Storage::new_runtime`.
+    pub fn register_runtime_component<T:Component>(&mut self) {
+        self.check_unshared();
         let id = T::type_id();
-        match self.singletons.get(&id) {
+        if self.components.contains_key(&id) {
+            panic!("{} component already registered!", type_name::<T>());
+        }
+        self.insert_storage(id, Storage::new_runtime::<T>());
+        self.add_required::<T>();
+        self.check_required_defaults();
+    }
+
+    /// Registers a component that is skipped by `serialize` but copied by `clone`.
+    pub fn register_runtime_component_cloned<T:Component + Clone>(&mut self) {
+        self.check_unshared();
+        let id = T::type_id();
+        if self.components.contains_key(&id) {
+            panic!("{} component already registered!", type_name::<T>());
+        }
+        self.insert_storage(id, Storage::new_runtime_cloned::<T>());
+        self.add_required::<T>();
+        self.check_required_defaults();
+    }
+
+    /// Makes attaching `T` also attach the component returned by `factory` when the entity lacks `R`.
+    pub fn require<T:Component, R:Component, F:Fn() -> R + 'static>(&mut self, factory:F) {
+        self.check_unshared();
+        if reaches(&self.required, R::type_id(), T::type_id()) {
+            panic!("{} component requirements form a cycle!", type_name::<T>());
+        }
+        let insert = Rc::new(move |registry:&mut Registry, id| registry.component_try_attach(id, factory()));
+        self.required.entry(T::type_id()).or_default().push(Required::new(R::type_id(), Some(insert)));
+    }
+
+    /// Makes attaching a component with missing requirements fail instead of inserting them.
+    pub fn set_strict_required(&mut self, strict:bool) {
+        self.strict_required = strict;
+    }
+
+    fn add_required<T:Component>(&mut self) {
+        let required = T::required();
+        if required.is_empty() {
+            return;
+        }
+        if required.iter().any(|component| reaches(&self.required, *component, T::type_id())) {
+            panic!("{} component requirements form a cycle!", type_name::<T>());
+        }
+        let entry = self.required.entry(T::type_id()).or_default();
+        entry.extend(required.into_iter().map(|component| Required::new(component, None)));
+    }
+
+    /// Components required without a factory are inserted with their default value, so they must have one.
+    /// Checked whenever a component is registered since requirements may be registered in any order.
+    fn check_required_defaults(&self) {
+        for (component, required) in self.required.iter() {
+            for required in required.iter().filter(|required| required.insert.is_none()) {
+                if let Some(storage) = self.components.get(&required.component).filter(|storage| !storage.has_default()) {
+                    let name = self.component_name(*component).map(str::to_string).unwrap_or_else(|| component.to_string());
+                    panic!("{} is required by {} but has no default value!", storage.name, name);
+                }
+            }
+        }
+    }
+
+    fn missing_required(&self, id:EntityId, component:Uuid) -> Option<Uuid> {
+        let required = self.required.get(&component)?;
+        required.iter().map(|required| required.component).find(|required| !self.component_has_dyn(id, *required))
+    }
+
+    fn insert_required(&mut self, id:EntityId, component:Uuid) -> Result<(), AttachError> {
+        let Some(required) = self.required.get(&component).cloned() else {
+            return Ok(());
+        };
+        for required in required {
+            if self.component_has_dyn(id, required.component) {
+                continue;
+            }
+            match required.insert {
+                Some(insert) => insert(self, id)?,
+                None => {
+                    let storage = self.components.get_mut(&required.component);
+                    let inserted = storage.is_some_and(|storage| {
+                        let inserted = storage.insert_default(id);
+                        storage.touch(id);
+                        inserted
+                    });
+                    if !inserted {
+                        let name = |uuid:Uuid| self.component_name(uuid).map(str::to_string).unwrap_or_else(|| uuid.to_string());
+                        return Err(AttachError::MissingRequired { component:name(component), required:name(required.component) });
+                    }
+                    self.insert_required(id, required.component)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn register_dynamic_component(&mut self, id:Uuid, schema:Schema) {
+        self.check_unshared();
+        if self.components.contains_key(&id) {
+            panic!("{} component already registered!", schema.name);
+        }
+        let mut storage = Storage::new_dynamic(schema);
+        storage.journaled = self.journal.is_some();
+        self.insert_storage(id, storage);
+        self.check_required_defaults();
+    }
+
+    pub fn register_relation<R:Relation>(&mut self) {
+        self.check_unshared();
+        let id = R::type_id();
+        if self.relations.contains_key(&id) {
+            panic!("{} relation already registered!", type_name::<R>());
+        }
+        self.relations.insert(id, RelationStorage::default());
+    }
+
+    /// Like `register_component` but `dump` prints the component with `Debug`.
+    pub fn register_debug_component<T:SerializableComponent + Debug>(&mut self) {
+        self.register_component::<T>();
+        self.register_debug::<T>();
+    }
+
+    pub fn register_replicated<T:Replicate>(&mut self) {
+        self.register_component::<T>();
+        self.component_storage_mut::<T>().replicated = true;
+    }
+
+    /// Makes `dump` print an already registered component or singleton with `Debug`.
+    pub fn register_debug<T:Component + Debug>(&mut self) {
+        self.check_unshared();
+        let id = T::type_id();
+        let mut registered = false;
+        if let Some(storage) = self.components.get_mut(&id) {
+            storage.set_debug::<T>();
+            registered = true;
+        }
+        if let Some(storage) = self.singletons.get_mut(&id) {
+            storage.set_debug::<T>();
+            registered = true;
+        }
+        if !registered {
+            panic!("{} type not registered!", type_name::<T>());
+        }
+    }
+
+    pub(crate) fn storages(&self) -> impl Iterator<Item = (&Uuid, &Storage)> {
+        self.components.iter()
+    }
+
+    pub(crate) fn singleton_storages(&self) -> impl Iterator<Item = (&Uuid, &Storage)> {
+        self.singletons.iter()
+    }
+
+    pub(crate) fn singleton_id(&self) -> EntityId {
+        self.singleton
+    }
+
+    pub(crate) fn replicated_storages(&self) -> impl Iterator<Item = (&Uuid, &Storage)> {
+        self.components.iter().filter(|(_, storage)| storage.replicated)
+    }
+
+    pub(crate) fn storage_by_id_mut(&mut self, id:&Uuid) -> Option<&mut Storage> {
+        self.components.get_mut(id)
+    }
+
+    fn relation_storage<R:Relation>(&self) -> &RelationStorage {
+        match self.relations.get(&R::type_id()) {
+            Some(storage) => storage,
+            None => panic!("{} relation type not registered!", type_name::<R>()),
+        }
+    }
+
+    fn relation_storage_mut<R:Relation>(&mut self) -> &mut RelationStorage {
+        match self.relations.get_mut(&R::type_id()) {
             Some(storage) => storage,
-            None => panic!("{} singleton type not registered!", type_name::<T>()),
+            None => panic!("{} relation type not registered!", type_name::<R>()),
         }
     }
 
-    unsafe fn component_storage_mut<T:Component>(&mut self) -> &mut Storage {
+    fn component_storage_mut<T:Component>(&mut self) -> &mut Storage {
         let id = T::type_id();
         match self.components.get_mut(&id) {
             Some(storage) => storage,
@@ -131,7 +572,7 @@ impl Registry {
         }
     }
 
-    unsafe fn component_storage<T:Component>(&self) -> &Storage {
+    fn component_storage<T:Component>(&self) -> &Storage {
         let id = T::type_id();
         match self.components.get(&id) {
             Some(storage) => storage,
@@ -139,137 +580,1012 @@ impl Registry {
         }
     }
 
-    pub fn components<T:Component>(&self) -> Components<T> {
+    pub fn components<T:Component>(&self) -> Components<'_, T> {
         let id = T::type_id();
         match self.components.get(&id) {
-            Some(storage) => unsafe { Components::new(storage) },
+            Some(storage) => {
+                let mut components = Components::new(storage, &self.disabled);
+                components.conflicts.strict = self.strict_borrows;
+                components
+            },
             None => panic!("{} component type not registered!", type_name::<T>()),
         }
     }
 
     pub fn component_attach<T:Component>(&mut self, id:EntityId, component:T) {
-        unsafe {
-            self.component_storage_mut::<T>().get_mut().insert(id, RefCell::new(component));
+        if let Err(err) = self.component_try_attach(id, component) {
+            panic!("failed to attach {}: {}", type_name::<T>(), err);
         }
     }
 
-    pub fn component_detach<T:Component>(&mut self, id:EntityId) -> Option<T> {
-        unsafe {
-            let cmp:Option<RefCell<T>> = self.component_storage_mut::<T>().get_mut().remove(id);
-            if let Some(cmp) = cmp {
-                return Some(cmp.into_inner());
+    pub fn component_try_attach<T:Component>(&mut self, id:EntityId, component:T) -> Result<(), AttachError> {
+        self.check_storage_type::<T>();
+        if self.strict_required {
+            if let Some(required) = self.missing_required(id, T::type_id()) {
+                let required = self.component_name(required).map(str::to_string).unwrap_or_else(|| required.to_string());
+                return Err(AttachError::MissingRequired { component:type_name::<T>().to_string(), required });
+            }
+        }
+        let previous = self.attach_component(id, component)?;
+        if !self.required.contains_key(&T::type_id()) {
+            return Ok(());
+        }
+        let present = self.component_uuids(id);
+        let result = self.insert_required(id, T::type_id());
+        if result.is_err() {
+            // undo the attach, including required components inserted before the failure
+            for component in self.component_uuids(id) {
+                if !present.contains(&component) {
+                    self.remove_component(id, component);
+                }
+            }
+            self.component_detach::<T>(id);
+            if let Some(previous) = previous {
+                let _ = self.attach_component(id, previous);
             }
-            None
         }
+        result
     }
 
-    pub fn component_mut<T:Component>(&self, id:EntityId) -> Option<RefMut<T>> {
-        unsafe {
-            let storage = self.component_storage::<T>().get();
-            let cmp:Option<&RefCell<T>> = storage.get(id);
-            if let Some(cmp) = cmp {
-                if let Ok(cmd) = cmp.try_borrow_mut() {
-                    return Some(cmd);
+    /// Panics unless `T` is registered and its storage holds `T`, before anything is checked or attached.
+    fn check_storage_type<T:Component>(&self) {
+        let storage = self.component_storage::<T>();
+        if storage.get::<T>().is_none() {
+            panic!("{} storage does not hold {}", storage.name, type_name::<T>());
+        }
+    }
+
+    /// Checks the unique constraints and inserts the component, returning the replaced one.
+    fn attach_component<T:Component>(&mut self, id:EntityId, component:T) -> Result<Option<T>, AttachError> {
+        self.refresh_indexes();
+        for index in self.indexes.get_mut().iter() {
+            if index.component() == T::type_id() && index.conflicts(id, &component) {
+                let other = index.holder(&component).unwrap_or_default();
+                return Err(AttachError::UniqueViolation { component:type_name::<T>().to_string(), entity:self.describe(other) });
+            }
+        }
+        if let Some(name) = (&component as &dyn std::any::Any).downcast_ref::<Name>() {
+            self.refresh_names();
+            if self.names.borrow().unique {
+                if let Some(other) = self.find_by_name(name.as_str()).filter(|other| *other != id) {
+                    return Err(AttachError::DuplicateName { name:name.0.clone(), entity:self.describe(other) });
                 }
             }
-            None
+            self.names.get_mut().insert(name.as_str(), id);
+        }
+        for index in self.indexes.get_mut().iter_mut() {
+            if index.component() == T::type_id() {
+                index.insert(id, &component);
+            }
+        }
+        let storage = self.component_storage_mut::<T>();
+        let previous = storage.typed_mut().insert(id, RefCell::new(component)).map(RefCell::into_inner);
+        storage.record(id);
+        Ok(previous)
+    }
+
+    /// Removes a component of any kind, unlike `component_detach_dyn` also runtime ones.
+    fn remove_component(&mut self, id:EntityId, component:Uuid) {
+        if let Some(storage) = self.components.get_mut(&component) {
+            storage.remove(id);
+            storage.touch(id);
         }
     }
 
-    pub fn component<T:Component>(&self, id:EntityId) -> Option<Ref<T>> {
-        unsafe {
-            let storage = self.component_storage::<T>().get();
-            let cmp:Option<&RefCell<T>> = storage.get(id);
-            if let Some(cmp) = cmp {
-                if let Ok(cmd) = cmp.try_borrow() {
-                    return Some(cmd);
+    pub fn component_detach<T:Component>(&mut self, id:EntityId) -> Option<T> {
+        let storage = self.component_storage_mut::<T>();
+        let cmp:Option<RefCell<T>> = storage.typed_mut().remove(id);
+        if let Some(cmp) = cmp {
+            storage.record(id);
+            let cmp = cmp.into_inner();
+            for index in self.indexes.get_mut().iter_mut() {
+                if index.component() == T::type_id() {
+                    index.remove(id);
                 }
             }
-            None
+            if (&cmp as &dyn std::any::Any).is::<Name>() {
+                self.names.get_mut().remove(id);
+            }
+            return Some(cmp);
         }
+        None
+    }
+
+    pub fn component_mut<T:Component>(&self, id:EntityId) -> Option<RefMut<'_, T>> {
+        self.try_component_mut(id).ok()
+    }
+
+    pub fn component<T:Component>(&self, id:EntityId) -> Option<Ref<'_, T>> {
+        self.try_component(id).ok()
+    }
+
+    pub fn try_component<T:Component>(&self, id:EntityId) -> Result<Ref<'_, T>, BorrowError> {
+        let storage = self.component_storage::<T>();
+        let cell = storage.typed::<T>().get(id).ok_or(BorrowError::Missing)?;
+        cell.try_borrow().map_err(|_| {
+            Conflicts::new(storage, self.strict_borrows).report(id);
+            BorrowError::Borrowed
+        })
+    }
+
+    pub fn try_component_mut<T:Component>(&self, id:EntityId) -> Result<RefMut<'_, T>, BorrowError> {
+        let storage = self.component_storage::<T>();
+        let cell = storage.typed::<T>().get(id).ok_or(BorrowError::Missing)?;
+        let component = cell.try_borrow_mut().map_err(|_| {
+            Conflicts::new(storage, self.strict_borrows).report(id);
+            BorrowError::Borrowed
+        })?;
+        storage.touch(id);
+        Ok(component)
+    }
+
+    /// Makes borrow conflicts in `component`, `component_mut` and component iterators panic in debug builds.
+    pub fn set_strict_borrows(&mut self, strict:bool) {
+        self.strict_borrows = strict;
+    }
+
+    /// Number of times `T` was skipped or not returned because it was already borrowed.
+    pub fn skipped<T:Component>(&self) -> usize {
+        self.component_storage::<T>().skipped()
     }
 
     pub fn component_has<T:Component>(&self, id:EntityId) -> bool {
-        unsafe {
-            let storage = self.component_storage::<T>().get();
-            let cmp:Option<&RefCell<T>> = storage.get(id);
-            if cmp.is_some() {
-                return true;
+        let storage = self.component_storage::<T>().typed::<T>();
+        let cmp:Option<&RefCell<T>> = storage.get(id);
+        if cmp.is_some() {
+            return true;
+        }
+        false
+    }
+
+    pub fn component_dyn(&self, id:EntityId, component:Uuid) -> Option<Value> {
+        self.components.get(&component)?.reflect_get(id)
+    }
+
+    pub fn set_component_dyn(&mut self, id:EntityId, component:Uuid, value:Value) -> Result<(), ReflectError> {
+        if !self.entities.contains(id) {
+            return Err(ReflectError::UnknownEntity(id));
+        }
+        let unique = self.has_unique(component);
+        let Some(storage) = self.components.get_mut(&component) else {
+            return Err(ReflectError::UnknownComponent(component));
+        };
+        let previous = if unique { storage.serialize_one(id) } else { None };
+        storage.reflect_set(id, value)?;
+        self.keep_unique(id, component, previous).map_err(ReflectError::Conflict)
+    }
+
+    /// Sets a component from the bytes of `serialize_one`, like `set_component_dyn` keeping unique keys unique.
+    pub(crate) fn set_component_bytes(&mut self, id:EntityId, component:Uuid, bytes:&[u8]) -> bincode::Result<()> {
+        let unique = self.has_unique(component);
+        let Some(storage) = self.components.get_mut(&component) else {
+            return Ok(());
+        };
+        let previous = if unique { storage.serialize_one(id) } else { None };
+        storage.deserialize_one(id, bytes)?;
+        self.keep_unique(id, component, previous).map_err(|err| Box::new(bincode::ErrorKind::Custom(err.to_string())))
+    }
+
+    pub(crate) fn has_unique(&self, component:Uuid) -> bool {
+        (component == Name::type_id() && self.names.borrow().unique) || self.indexes.borrow().iter().any(|index| index.component() == component && index.unique())
+    }
+
+    /// Touches a component that was just written, or restores `previous` if the new value
+    /// takes a unique key or name held by another entity.
+    fn keep_unique(&mut self, id:EntityId, component:Uuid, previous:Option<Vec<u8>>) -> Result<(), AttachError> {
+        let storage = &self.components[&component];
+        let Some(err) = self.unique_conflict(component, storage, id, id) else {
+            storage.touch(id);
+            return Ok(());
+        };
+        let storage = self.components.get_mut(&component).expect("storage vanished");
+        match previous {
+            Some(bytes) => storage.deserialize_one(id, &bytes).expect("failed to restore component"),
+            None => {
+                storage.remove(id);
+            }
+        }
+        Err(err)
+    }
+
+    /// Error if an entity other than `except` holds the unique key or name of the component `storage`
+    /// holds for `id`. `storage` may belong to another registry with the same components registered.
+    fn unique_conflict(&self, component:Uuid, storage:&Storage, id:EntityId, except:EntityId) -> Option<AttachError> {
+        self.refresh_indexes();
+        for index in self.indexes.borrow().iter().filter(|index| index.component() == component) {
+            if let Some(other) = index.holder_of(storage, id, except) {
+                return Some(AttachError::UniqueViolation { component:storage.name.clone(), entity:self.describe(other) });
+            }
+        }
+        if component == Name::type_id() && self.names.borrow().unique {
+            let name = storage.typed::<Name>().get(id)?.try_borrow().ok()?.0.clone();
+            if let Some(other) = self.find_all_by_name(&name).into_iter().find(|other| *other != except) {
+                return Some(AttachError::DuplicateName { name, entity:self.describe(other) });
+            }
+        }
+        None
+    }
+
+    /// First unique key or name shared by two of `ids` in `scratch`, a storage of `component`
+    /// filled outside the registry.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn scratch_conflict(&self, component:Uuid, scratch:&Storage, ids:&[EntityId]) -> Option<AttachError> {
+        for index in self.indexes.borrow().iter().filter(|index| index.component() == component && index.unique()) {
+            let mut index = index.clone_box();
+            index.rebuild(scratch);
+            if let Some(other) = ids.iter().find_map(|id| index.holder_of(scratch, *id, *id)) {
+                return Some(AttachError::UniqueViolation { component:scratch.name.clone(), entity:format!("{:?}", other) });
+            }
+        }
+        if component == Name::type_id() && self.names.borrow().unique {
+            let mut names = FxHashSet::default();
+            for (id, name) in ids.iter().filter_map(|id| Some((*id, scratch.typed::<Name>().get(*id)?.borrow().0.clone()))) {
+                if !names.insert(name.clone()) {
+                    return Some(AttachError::DuplicateName { name, entity:format!("{:?}", id) });
+                }
             }
-            false
         }
+        None
+    }
+
+    /// First unique key or name of `id` in `source` that is already taken here.
+    pub(crate) fn unique_conflicts(&self, source:&Registry, id:EntityId) -> Option<AttachError> {
+        source.components.iter()
+            .filter(|(_, storage)| storage.has(id))
+            .find_map(|(uuid, storage)| self.unique_conflict(*uuid, storage, id, EntityId::default()))
+    }
+
+    pub fn component_has_dyn(&self, id:EntityId, component:Uuid) -> bool {
+        match self.components.get(&component) {
+            Some(storage) => storage.has(id),
+            None => false
+        }
+    }
+
+    pub fn component_detach_dyn(&mut self, id:EntityId, component:Uuid) -> Option<Value> {
+        let storage = self.components.get_mut(&component)?;
+        let value = storage.reflect_get(id)?;
+        storage.remove(id);
+        storage.touch(id);
+        Some(value)
+    }
+
+    pub fn iter_with<'a>(&'a self, components:&[Uuid]) -> impl Iterator<Item = EntityId> + 'a {
+        let storages:Option<Vec<&Storage>> = components.iter().map(|id| self.components.get(id)).collect();
+        let storages = storages.unwrap_or_default();
+        let empty = storages.is_empty() && !components.is_empty();
+        self.iter().filter(move |id| !empty && storages.iter().all(|storage| storage.has(*id)))
+    }
+
+    pub fn component_uuids(&self, id:EntityId) -> Vec<Uuid> {
+        self.components.iter().filter(|(_, storage)| storage.has(id)).map(|(uuid, _)| *uuid).collect()
+    }
+
+    pub fn component_name(&self, component:Uuid) -> Option<&str> {
+        self.components.get(&component).map(|storage| storage.name.as_str())
+    }
+
+    pub fn component_uuid(&self, name:&str) -> Option<Uuid> {
+        if let Some((id, _)) = self.components.iter().find(|(_, storage)| storage.name == name) {
+            return Some(*id);
+        }
+        Uuid::parse_str(name).ok().filter(|id| self.components.contains_key(id))
+    }
+
+    pub fn component_default_dyn(&self, component:Uuid) -> Option<Value> {
+        self.components.get(&component).map(|storage| storage.reflect_default())
+    }
+
+    pub fn relate<R:Relation>(&mut self, source:EntityId, target:EntityId) {
+        if !self.entities.contains(source) || !self.entities.contains(target) {
+            return;
+        }
+        self.relation_storage_mut::<R>().relate(source, target);
+        self.log(Record::Relate(R::type_id(), source, target));
+    }
+
+    pub fn unrelate<R:Relation>(&mut self, source:EntityId, target:EntityId) -> bool {
+        let unrelated = self.relation_storage_mut::<R>().unrelate(source, target);
+        if unrelated {
+            self.log(Record::Unrelate(R::type_id(), source, target));
+        }
+        unrelated
+    }
+
+    pub fn is_related<R:Relation>(&self, source:EntityId, target:EntityId) -> bool {
+        self.relation_storage::<R>().contains(source, target)
+    }
+
+    pub fn targets<R:Relation>(&self, source:EntityId) -> &[EntityId] {
+        self.relation_storage::<R>().targets(source)
+    }
+
+    pub fn sources<R:Relation>(&self, target:EntityId) -> &[EntityId] {
+        self.relation_storage::<R>().sources(target)
+    }
+
+    pub fn spawn(&mut self) -> EntityMut<'_> {
+        self.flush_entities();
+        let id = self.entities.alloc();
+        self.log(Record::Spawn(id));
+        EntityMut::new(id, self)
+    }
+
+    /// Reserves an id without spawning it. Reserved ids become alive on the next `execute`,
+    /// `spawn`, `despawn`, `clear`, `clone` or `serialize`, whichever comes first.
+    pub fn reserve_entity(&self) -> EntityId {
+        self.entities.reserve()
     }
 
-    pub fn spawn(&mut self) -> EntityMut {
-        let id = self.entities.insert(());
-        return EntityMut::new(id, self);
+    /// Reserves `count` ids at once, see `reserve_entity`.
+    pub fn reserve_entities(&self, count:usize) -> Vec<EntityId> {
+        self.entities.reserve_many(count)
+    }
+
+    /// Makes exactly `id` alive, used when loading entities saved outside the registry.
+    pub(crate) fn spawn_at(&mut self, id:EntityId) {
+        self.flush_entities();
+        self.entities.alloc_at(id);
+        self.log(Record::Spawn(id));
     }
 
     pub fn despawn(&mut self, id:EntityId) {
-        self.entities.remove(id);
+        self.names.get_mut().remove(id);
+        self.flush_entities();
+        if self.entities.free(id) {
+            self.log(Record::Despawn(id));
+        }
+        self.disabled.remove(id);
         for (_, storage) in self.components.iter_mut() {
             storage.remove(id);
         }
+        for index in self.indexes.get_mut().iter_mut() {
+            index.remove(id);
+        }
+        for (_, relation) in self.relations.iter_mut() {
+            relation.remove(id);
+        }
+    }
+
+    /// Despawns `id` and returns its components and enabled state for `put_entity`.
+    pub(crate) fn take_entity(&mut self, id:EntityId) -> Option<(TakenComponents, bool)> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.names.get_mut().remove(id);
+        let enabled = self.is_enabled(id);
+        let components = self.components.iter_mut().filter_map(|(uuid, storage)| Some((*uuid, storage.take(id)?))).collect();
+        self.despawn(id);
+        Some((components, enabled))
+    }
+
+    /// Spawns an entity holding components returned by `take_entity` of a registry with the same components registered,
+    /// unless one of them takes a unique key or name held here.
+    pub(crate) fn put_entity(&mut self, components:TakenComponents, enabled:bool) -> Result<EntityId, AttachError> {
+        self.refresh_indexes();
+        for (uuid, component) in components.iter() {
+            if let Some(index) = self.indexes.get_mut().iter().find(|index| index.component() == *uuid && index.conflicts(EntityId::default(), &**component)) {
+                let other = index.holder(&**component).unwrap_or_default();
+                let component = self.component_name(*uuid).unwrap_or_default().to_string();
+                return Err(AttachError::UniqueViolation { component, entity:self.describe(other) });
+            }
+            if let Some(name) = component.downcast_ref::<Name>().filter(|_| self.names.get_mut().unique) {
+                if let Some(other) = self.find_by_name(name.as_str()) {
+                    return Err(AttachError::DuplicateName { name:name.0.clone(), entity:self.describe(other) });
+                }
+            }
+        }
+        let id = self.spawn().id();
+        for (uuid, component) in components {
+            let Some(storage) = self.components.get_mut(&uuid) else {
+                panic!("component {} not registered!", uuid);
+            };
+            if storage.put(id, component).is_err() {
+                panic!("{} storage holds another type!", storage.name);
+            }
+            storage.touch(id);
+        }
+        if !enabled {
+            self.disable(id);
+        }
+        Ok(id)
     }
 
     pub fn serialize(&mut self, bytes:&mut Vec<u8>) {
+        if self.codec != Codec::None {
+            return self.serialize_to(bytes).expect("failed to serialize Registry");
+        }
+        self.flush_entities();
         let mut serialized_components =HashMap::new();
-        for (id, storage) in self.components.iter() {
+        for (id, storage) in self.components.iter().filter(|(_, storage)| storage.serialized) {
             let mut bytes = Vec::new();
-            unsafe {
-                storage.serialize(&mut bytes);
-                serialized_components.insert(*id, bytes);
-            }
+            storage.serialize(&mut bytes);
+            serialized_components.insert(*id, bytes);
         }
         let mut serialized_singletons =HashMap::new();
-        for (id, storage) in self.singletons.iter() {
+        for (id, storage) in self.singletons.iter().filter(|(_, storage)| storage.serialized) {
             let mut bytes = Vec::new();
-            unsafe {
-                storage.serialize(&mut bytes);
-                serialized_singletons.insert(*id, bytes);
-            }
+            storage.serialize(&mut bytes);
+            serialized_singletons.insert(*id, bytes);
+        }
+      
+        let mut serialized_relations = HashMap::new();
+        for (id, relation) in self.relations.iter() {
+            let mut bytes = Vec::new();
+            relation.serialize(&mut bytes);
+            serialized_relations.insert(*id, bytes);
         }
       
         let w = SerializableRegistry {
-            entities:self.entities.clone(),
+            entities:EntitySlots::from(&self.entities),
             serialized_components,
             serialized_singletons
         };
+        let additions = SerializableAdditions {
+            disabled:self.disabled.clone(),
+            serialized_relations
+        };
 
-        let writer = BufWriter::new(bytes);
-        bincode::serialize_into(writer, &w).expect("failed to serialize Registry");
+        let mut writer = BufWriter::new(bytes);
+        bincode::serialize_into(&mut writer, &w).expect("failed to serialize Registry");
+        bincode::serialize_into(&mut writer, &additions).expect("failed to serialize Registry");
+        let timers = self.timers.get_mut().serialize().expect("failed to serialize timers");
+        writer.write_all(&timers).expect("failed to serialize Registry");
     }
 
     pub fn deserialize(&mut self, bytes:&[u8]) {
-        let w:SerializableRegistry = bincode::deserialize(bytes).expect("failed to deserialize Registry");
-        self.entities = w.entities;
+        if bytes.starts_with(MAGIC) {
+            return self.deserialize_from(bytes).expect("failed to deserialize Registry");
+        }
+        let mut reader = bytes;
+        let w:SerializableRegistry = bincode::deserialize_from(&mut reader).expect("failed to deserialize Registry");
+        let additions:SerializableAdditions = match reader.is_empty() {
+            true => SerializableAdditions::default(),
+            false => bincode::deserialize_from(&mut reader).expect("failed to deserialize Registry")
+        };
+        self.reset_journal(0);
+        match reader.is_empty() {
+            true => self.timers.get_mut().reset(),
+            false => self.timers.get_mut().deserialize(reader).expect("failed to deserialize timers")
+        }
+        self.entities = w.entities.into();
+        self.disabled = additions.disabled;
+        self.names.get_mut().dirty = true;
         for (id, bytes) in w.serialized_components.iter() {
             if let Some(storage) = self.components.get_mut(id) {
-                unsafe {
-                    storage.deserialize(bytes);
-                }
+                storage.deserialize(bytes);
             }
         }
+        for (_, storage) in self.components.iter_mut().filter(|(_, storage)| !storage.serialized) {
+            storage.clear();
+        }
         for (id, bytes) in w.serialized_singletons.iter() {
             if let Some(storage) = self.singletons.get_mut(id) {
-                unsafe {
-                    storage.deserialize(bytes);
+                storage.deserialize(bytes);
+            }
+        }
+        for (id, relation) in self.relations.iter_mut() {
+            match additions.serialized_relations.get(id) {
+                Some(bytes) => relation.deserialize(&mut bytes.as_slice()).expect("failed to deserialize relation"),
+                None => relation.clear()
+            }
+        }
+        self.rebuild_indexes();
+    }
+
+    /// Compresses every section written by `serialize_to`, and by `serialize` which then uses the same format.
+    pub fn set_codec(&mut self, codec:Codec) {
+        self.codec = codec;
+    }
+
+    /// Writes the registry section by section, each storage is serialized straight into `writer`
+    /// unless a codec is set, then every section is compressed on its own.
+    pub fn serialize_to<W:Write>(&mut self, writer:W) -> io::Result<()> {
+        self.flush_entities();
+        let codec = self.codec;
+        let mut writer = BufWriter::new(writer);
+        write_header(&mut writer, codec)?;
+        write_compressed(&mut writer, codec, Section::Entities, Uuid::nil(), bincode::serialized_size(&self.entities).map_err(bincode_error)?, |writer| {
+            bincode::serialize_into(writer, &self.entities).map_err(bincode_error)
+        })?;
+        write_compressed(&mut writer, codec, Section::Disabled, Uuid::nil(), bincode::serialized_size(&self.disabled).map_err(bincode_error)?, |writer| {
+            bincode::serialize_into(writer, &self.disabled).map_err(bincode_error)
+        })?;
+        for (id, storage) in self.components.iter().filter(|(_, storage)| storage.serialized) {
+            write_compressed(&mut writer, codec, Section::Component, *id, storage.serialized_size(), |writer| {
+                storage.serialize_to(writer).map_err(bincode_error)
+            })?;
+        }
+        for (id, storage) in self.singletons.iter().filter(|(_, storage)| storage.serialized) {
+            write_compressed(&mut writer, codec, Section::Singleton, *id, storage.serialized_size(), |writer| {
+                storage.serialize_to(writer).map_err(bincode_error)
+            })?;
+        }
+        for (id, relation) in self.relations.iter() {
+            let mut bytes = Vec::new();
+            relation.serialize(&mut bytes);
+            write_compressed(&mut writer, codec, Section::Relation, *id, bytes.len() as u64, |writer| writer.write_all(&bytes))?;
+        }
+        let timers = self.timers.get_mut().serialize().map_err(bincode_error)?;
+        write_compressed(&mut writer, codec, Section::Timers, Uuid::nil(), timers.len() as u64, |writer| writer.write_all(&timers))?;
+        if let Some(journal) = &self.journal {
+            let generation = journal.generation.to_le_bytes();
+            write_compressed(&mut writer, codec, Section::Journal, Uuid::nil(), 8, |writer| writer.write_all(&generation))?;
+        }
+        write_section(&mut writer, Section::End, Uuid::nil(), 0)?;
+        writer.flush()
+    }
+
+    /// Reads a registry written by `serialize_to` with whichever codec its header names,
+    /// sections of unregistered types are skipped without being decompressed.
+    /// `reader` is read in small pieces and should be buffered. A stream that is not a registry
+    /// leaves the registry as it is, one that fails after its header leaves it cleared like `clear`.
+    /// Compressed component sections are kept as they are and inflated on the first access to
+    /// their storage, see `inflate`.
+    pub fn deserialize_from<R:Read>(&mut self, mut reader:R) -> io::Result<()> {
+        let codec = read_header(&mut reader)?;
+        self.reset_journal(0);
+        self.timers.get_mut().reset();
+        if let Err(err) = self.read_sections(&mut reader, codec) {
+            self.clear();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Inflates the component sections `deserialize_from` left compressed, returns the error of the
+    /// first corrupt section. Storages of corrupt sections are left empty.
+    pub fn inflate(&self) -> io::Result<()> {
+        let mut result = Ok(());
+        for (_, storage) in self.components.iter() {
+            if let Err(err) = storage.inflate() {
+                if result.is_ok() {
+                    result = Err(err);
                 }
             }
         }
+        result
+    }
+
+    /// Whether the components of `T` were loaded, false while their section is still compressed.
+    pub fn is_inflated<T:Component>(&self) -> bool {
+        self.component_storage::<T>().is_inflated()
+    }
+
+    fn read_sections(&mut self, reader:&mut dyn Read, codec:Codec) -> io::Result<()> {
+        let mut relations = FxHashSet::default();
+        loop {
+            let (section, id, len) = read_section(reader)?;
+            let mut payload = (&mut *reader).take(len);
+            match section {
+                Section::End => break,
+                Section::Entities => {
+                    self.entities = read_compressed(&mut payload, codec, |reader| bincode::deserialize_from(reader).map_err(bincode_error))?;
+                    self.entities.reset_cursor();
+                },
+                Section::Disabled => {
+                    self.disabled = read_compressed(&mut payload, codec, |reader| bincode::deserialize_from(reader).map_err(bincode_error))?;
+                },
+                Section::Component => {
+                    match self.components.get_mut(&id) {
+                        Some(storage) if codec != Codec::None => {
+                            let mut bytes = Vec::new();
+                            payload.read_to_end(&mut bytes)?;
+                            storage.pack(codec, bytes);
+                        },
+                        Some(storage) => storage.deserialize_from(&mut payload).map_err(bincode_error)?,
+                        None => ()
+                    }
+                },
+                Section::Singleton => {
+                    if let Some(storage) = self.singletons.get_mut(&id) {
+                        read_compressed(&mut payload, codec, |reader| storage.deserialize_from(reader).map_err(bincode_error))?;
+                    }
+                },
+                Section::Relation => {
+                    if let Some(relation) = self.relations.get_mut(&id) {
+                        read_compressed(&mut payload, codec, |reader| relation.deserialize(reader).map_err(bincode_error))?;
+                        relations.insert(id);
+                    }
+                },
+                Section::Journal => {
+                    let generation = read_compressed(&mut payload, codec, |reader| {
+                        let mut bytes = [0; 8];
+                        reader.read_exact(&mut bytes)?;
+                        Ok(u64::from_le_bytes(bytes))
+                    })?;
+                    self.reset_journal(generation);
+                },
+                Section::Timers => {
+                    let timers = read_compressed(&mut payload, codec, |reader| {
+                        let mut bytes = Vec::new();
+                        reader.read_to_end(&mut bytes)?;
+                        Ok(bytes)
+                    })?;
+                    self.timers.get_mut().deserialize(&timers).map_err(bincode_error)?;
+                }
+            }
+            io::copy(&mut payload, &mut io::sink())?;
+        }
+        self.names.get_mut().dirty = true;
+        for (_, storage) in self.components.iter_mut().filter(|(_, storage)| !storage.serialized) {
+            storage.clear();
+        }
+        for (_, relation) in self.relations.iter_mut().filter(|(id, _)| !relations.contains(id)) {
+            relation.clear();
+        }
+        self.rebuild_indexes();
+        Ok(())
+    }
+
+    /// Starts recording spawns, despawns, relations and changes of serialized components and
+    /// singletons for `write_journal`.
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_some() {
+            return;
+        }
+        self.flush_entities();
+        self.journal = Some(JournalLog::default());
+        for (_, storage) in self.components.iter_mut().chain(self.singletons.iter_mut()).filter(|(_, storage)| storage.serialized) {
+            storage.journaled = true;
+        }
+    }
+
+    /// Enables journaling and starts a journal for the last snapshot written by `compact`.
+    pub fn start_journal<W:Write>(&mut self, writer:W) -> io::Result<Journal<W>> {
+        self.enable_journal();
+        let generation = self.journal.as_ref().map(|journal| journal.generation).unwrap_or_default();
+        Journal::new(writer, generation)
+    }
+
+    /// Appends the changes made since the last write and flushes `journal`, returns the number of records written.
+    /// Components are written with their current value, no matter how often they changed in between.
+    pub fn write_journal<W:Write>(&mut self, journal:&mut Journal<W>) -> io::Result<usize> {
+        self.flush_entities();
+        let Some(log) = &mut self.journal else {
+            panic!("journal not enabled!");
+        };
+        if log.generation != journal.generation() {
+            panic!("journal was started before the last compaction!");
+        }
+        let records = journal.records();
+        for record in log.records.drain(..) {
+            journal.append(&record)?;
+        }
+        for (uuid, storage) in self.components.iter().filter(|(_, storage)| storage.journaled) {
+            let changed:Vec<EntityId> = storage.changed.borrow_mut().drain().map(|(id, _)| id).collect();
+            for id in changed.into_iter().filter(|id| self.entities.contains(*id)) {
+                match storage.serialize_one(id) {
+                    Some(bytes) => journal.append(&Record::Set(id, *uuid, bytes))?,
+                    None => journal.append(&Record::Remove(id, *uuid))?
+                }
+            }
+        }
+        for (uuid, storage) in self.singletons.iter().filter(|(_, storage)| storage.journaled) {
+            if storage.changed.borrow_mut().drain().next().is_some() {
+                if let Some(bytes) = storage.serialize_one(self.singleton) {
+                    journal.append(&Record::Singleton(*uuid, bytes))?;
+                }
+            }
+        }
+        journal.flush()?;
+        Ok(journal.records() - records)
+    }
+
+    /// Writes a full snapshot that supersedes all existing journals, start a new journal with
+    /// `start_journal` once the snapshot is safely stored.
+    pub fn compact<W:Write>(&mut self, snapshot:W) -> io::Result<()> {
+        self.enable_journal();
+        self.flush_entities();
+        let generation = self.journal.as_ref().map(|journal| journal.generation + 1).unwrap_or_default();
+        self.reset_journal(generation);
+        self.serialize_to(snapshot)
+    }
+
+    /// Loads a snapshot written by `compact` and replays the journal started after it, returns
+    /// the number of records replayed. Replay stops at the first incomplete or corrupt record,
+    /// a journal belonging to an older snapshot is ignored.
+    pub fn recover<S:Read, J:Read>(&mut self, snapshot:S, mut journal:J) -> io::Result<usize> {
+        self.enable_journal();
+        self.deserialize_from(snapshot)?;
+        let generation = self.journal.as_ref().map(|journal| journal.generation).unwrap_or_default();
+        if read_journal_header(&mut journal)? != Some(generation) {
+            return Ok(0);
+        }
+        let mut replayed = 0;
+        while let Some(record) = read_record(&mut journal)? {
+            match record {
+                Record::Spawn(id) => self.spawn_at(id),
+                Record::Despawn(id) => self.despawn(id),
+                Record::Disable(id) => self.disable(id),
+                Record::Enable(id) => self.enable(id),
+                Record::Clear => self.clear(),
+                Record::Set(id, component, bytes) => {
+                    if let Some(storage) = self.components.get_mut(&component) {
+                        storage.deserialize_one(id, &bytes).map_err(bincode_error)?;
+                    }
+                },
+                Record::Remove(id, component) => {
+                    if let Some(storage) = self.components.get_mut(&component) {
+                        storage.remove(id);
+                    }
+                },
+                Record::Relate(relation, source, target) => {
+                    if let Some(relation) = self.relations.get_mut(&relation) {
+                        relation.relate(source, target);
+                    }
+                },
+                Record::Unrelate(relation, source, target) => {
+                    if let Some(relation) = self.relations.get_mut(&relation) {
+                        relation.unrelate(source, target);
+                    }
+                },
+                Record::Singleton(singleton, bytes) => {
+                    if let Some(storage) = self.singletons.get_mut(&singleton) {
+                        storage.deserialize_one(self.singleton, &bytes).map_err(bincode_error)?;
+                    }
+                },
+                Record::RemoveSingleton(singleton) => {
+                    self.reset_singletons.remove(&singleton);
+                    self.singletons.remove(&singleton);
+                }
+            }
+            replayed += 1;
+        }
+        self.reset_journal(generation);
+        self.names.get_mut().dirty = true;
+        self.rebuild_indexes();
+        Ok(replayed)
+    }
+
+    fn log(&mut self, record:Record) {
+        if let Some(journal) = &mut self.journal {
+            journal.records.push(record);
+        }
+    }
+
+    /// Drops recorded changes, the registry was replaced or saved in full.
+    fn reset_journal(&mut self, generation:u64) {
+        if let Some(journal) = &mut self.journal {
+            journal.generation = generation;
+            journal.records.clear();
+            for (_, storage) in self.components.iter().chain(self.singletons.iter()) {
+                storage.changed.borrow_mut().clear();
+            }
+        }
+    }
+
+    /// Makes reserved entities alive, recording them in the journal.
+    fn flush_entities(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.records.extend(self.entities.reserved().map(Record::Spawn));
+        }
+        self.entities.flush();
     }
 
     pub fn clear(&mut self) {
+        self.flush_entities();
+        self.log(Record::Clear);
         self.entities.clear();
+        self.disabled.clear();
+        self.names.get_mut().clear();
         for (_, storage) in self.components.iter_mut() {
             storage.clear();
         }
-        for (_, storage) in self.singletons.iter_mut() {
+        for (_, storage) in self.singletons.iter_mut().filter(|(id, _)| self.reset_singletons.contains(id)) {
             storage.default(self.singleton);
         }
+        for (_, relation) in self.relations.iter_mut() {
+            relation.clear();
+        }
+        self.rebuild_indexes();
     }
 
     pub fn clone(&mut self) -> Self {
-        Self { entities: self.entities.clone(), components: self.components.clone(), singletons: self.singletons.clone(), singleton:self.singleton, commands:RefCell::new(Commands::default()) }
+        self.flush_entities();
+        Self { entities: self.entities.clone(), disabled: self.disabled.clone(), components: self.components.clone(), singletons: self.singletons.clone(), reset_singletons:self.reset_singletons.clone(), relations: self.relations.clone(), singleton:self.singleton, commands:RefCell::new(Commands::default()), names:self.names.clone(), indexes:RefCell::new(self.indexes.borrow().iter().map(|index| index.clone_box()).collect()), strict_borrows:self.strict_borrows, required:self.required.clone(), strict_required:self.strict_required, codec:self.codec, journal:None, timers:RefCell::new(self.timers.borrow().clone()), shared:false }
+    }
+
+    pub fn stats(&self) -> RegistryStats {
+        let mut components:Vec<_> = self.components.iter().map(|(uuid, storage)| StorageStats::new(*uuid, storage)).collect();
+        components.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut singletons:Vec<_> = self.singletons.iter().map(|(uuid, storage)| StorageStats::new(*uuid, storage)).collect();
+        singletons.sort_by(|a, b| a.name.cmp(&b.name));
+        RegistryStats {
+            entities:self.entities.len(),
+            entity_capacity:self.entities.capacity(),
+            free_entities:self.entities.free_len(),
+            reserved_entities:self.entities.pending(),
+            entity_bytes:self.entities.bytes(),
+            components,
+            singletons,
+            pending_commands:self.commands.borrow().len()
+        }
+    }
+
+    /// Pre-sizes the storage of `T` and the entity allocator for `additional` more entities.
+    pub fn reserve<T:Component>(&mut self, additional:usize) {
+        self.entities.reserve_slots(additional);
+        let capacity = self.entities.slots() + additional;
+        self.component_storage_mut::<T>().reserve(capacity);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.flush_entities();
+        self.entities.shrink_to_fit();
+        for (_, storage) in self.components.iter_mut() {
+            storage.shrink_to_fit();
+        }
+        self.disabled = self.disabled.drain().collect();
+    }
+
+    pub fn create_index<T:Component, K:Ord + Clone + 'static>(&mut self, key:impl Fn(&T) -> K + 'static) -> IndexHandle<T, K> {
+        IndexHandle::new(self.add_index::<T>(Box::new(Index::new(key, false))))
+    }
+
+    pub fn create_unique_index<T:Component, K:Ord + Clone + 'static>(&mut self, key:impl Fn(&T) -> K + 'static) -> IndexHandle<T, K> {
+        IndexHandle::new(self.add_index::<T>(Box::new(Index::new(key, true))))
+    }
+
+    pub fn create_spatial_index<T:Component>(&mut self, cell_size:f32, position:impl Fn(&T) -> [f32; 2] + 'static) -> SpatialHandle<T> {
+        SpatialHandle::new(self.add_index::<T>(Box::new(SpatialIndex::new(cell_size, position))))
+    }
+
+    pub fn index<T:Component, K:Ord + Clone + 'static>(&self, handle:&IndexHandle<T, K>) -> Ref<'_, Index<T, K>> {
+        self.refresh_indexes();
+        Ref::map(self.indexes.borrow(), |indexes| {
+            indexes[handle.index].as_any().downcast_ref::<Index<T, K>>().expect("index handle does not belong to this registry")
+        })
+    }
+
+    pub fn spatial_index<T:Component>(&self, handle:&SpatialHandle<T>) -> Ref<'_, SpatialIndex<T>> {
+        self.refresh_indexes();
+        Ref::map(self.indexes.borrow(), |indexes| {
+            indexes[handle.index].as_any().downcast_ref::<SpatialIndex<T>>().expect("spatial handle does not belong to this registry")
+        })
+    }
+
+    fn add_index<T:Component>(&mut self, mut index:Box<dyn ComponentIndex>) -> usize {
+        self.check_unshared();
+        let storage = self.component_storage_mut::<T>();
+        storage.tracked = true;
+        index.rebuild(storage);
+        let indexes = self.indexes.get_mut();
+        indexes.push(index);
+        indexes.len() - 1
+    }
+
+    /// Applies pending changes to all indexes and the name index at once. While an index is borrowed
+    /// the indexes are left as they are and the changes stay pending for the next lookup.
+    fn refresh_indexes(&self) {
+        let mut indexes = self.indexes.try_borrow_mut().ok();
+        let mut names = self.names.try_borrow_mut().ok();
+        for (uuid, storage) in self.components.iter().filter(|(_, storage)| storage.tracked) {
+            let touched:Vec<EntityId> = storage.touched.borrow_mut().drain().map(|(id, _)| id).collect();
+            for id in touched.iter().copied() {
+                let mut refreshed = indexes.is_some();
+                for index in indexes.iter_mut().flat_map(|indexes| indexes.iter_mut()).filter(|index| index.component() == *uuid) {
+                    refreshed &= index.refresh(storage, id);
+                }
+                if *uuid == Name::type_id() {
+                    refreshed &= names.as_mut().is_some_and(|names| names.refresh(storage, id));
+                }
+                if !refreshed {
+                    storage.touch(id);
+                }
+            }
+            if cfg!(debug_assertions) {
+                // changes through `component_mut` and iterators are not checked when they are made
+                for id in touched {
+                    let mut unique = indexes.iter().flat_map(|indexes| indexes.iter()).filter(|index| index.component() == *uuid);
+                    if let Some(other) = unique.find_map(|index| index.holder_of(storage, id, id)) {
+                        panic!("{} of {:?} takes the unique key of {:?}!", storage.name, id, other);
+                    }
+                    if let Some(names) = names.as_ref().filter(|names| names.unique && *uuid == Name::type_id()) {
+                        if let Some(name) = names.by_id.get(id).filter(|name| names.get(name).len() > 1) {
+                            panic!("name '{}' of {:?} is not unique!", name, id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn rebuild_indexes(&mut self) {
+        for index in self.indexes.get_mut().iter_mut() {
+            if let Some(storage) = self.components.get(&index.component()) {
+                storage.touched.borrow_mut().clear();
+                index.rebuild(storage);
+            }
+        }
+    }
+
+    pub fn set_unique_names(&mut self, unique:bool) {
+        self.names.get_mut().unique = unique;
+    }
+
+    pub fn find_by_name(&self, name:&str) -> Option<EntityId> {
+        self.find_all_by_name(name).into_iter().next()
+    }
+
+    pub fn find_all_by_name(&self, name:&str) -> Vec<EntityId> {
+        self.refresh_names();
+        let names = self.names.borrow();
+        names.get(name).iter().copied().filter(|id| self.has_name(*id, name)).collect()
+    }
+
+    /// Finds an entity by a `/` separated path of names, following `Parent` links up to a root entity.
+    pub fn find_by_path(&self, path:&str) -> Option<EntityId> {
+        let segments:Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        let (last, ancestors) = segments.split_last()?;
+        self.find_all_by_name(last).into_iter().find(|id| {
+            let mut current = *id;
+            for segment in ancestors.iter().rev() {
+                match self.component::<Parent>(current).map(|parent| parent.0) {
+                    Some(parent) if self.has_name(parent, segment) => current = parent,
+                    _ => return false
+                }
+            }
+            !self.component_has::<Parent>(current)
+        })
+    }
+
+    pub fn describe(&self, id:EntityId) -> String {
+        match self.component::<Name>(id) {
+            Some(name) => format!("{:?} \"{}\"", id, name),
+            None => format!("{:?}", id)
+        }
+    }
+
+    /// Type names address components in scripts and queries, so they have to be unique.
+    fn insert_storage(&mut self, id:Uuid, storage:Storage) {
+        if self.components.values().any(|other| other.name == storage.name) {
+            panic!("a component named {} is already registered!", storage.name);
+        }
+        self.components.insert(id, storage);
+    }
+
+    /// Worlds of a `Universe` share their registrations, which therefore only go through `Universe::register`.
+    pub(crate) fn set_shared(&mut self, shared:bool) {
+        self.shared = shared;
+    }
+
+    fn check_unshared(&self) {
+        if self.shared {
+            panic!("registrations of a universe world must go through Universe::register!");
+        }
+    }
+
+    /// First component of `id` that `target` has no storage of the same type for.
+    pub(crate) fn unmovable(&self, id:EntityId, target:&Registry) -> Option<Uuid> {
+        self.components.iter()
+            .filter(|(_, storage)| storage.has(id))
+            .find(|(uuid, storage)| !target.components.get(uuid).is_some_and(|other| other.same_type(storage)))
+            .map(|(uuid, _)| *uuid)
+    }
+
+    fn has_name(&self, id:EntityId, name:&str) -> bool {
+        self.entities.contains(id) && self.component::<Name>(id).is_some_and(|other| other.as_str() == name)
+    }
+
+    /// Rebuilds the name index after bulk changes like `deserialize`, other changes arrive
+    /// through the touched entries of the `Name` storage.
+    fn refresh_names(&self) {
+        if self.names.borrow().dirty {
+            let mut names = self.names.borrow_mut();
+            names.clear();
+            for (id, name) in self.component_storage::<Name>().typed::<Name>().iter() {
+                if let Ok(name) = name.try_borrow() {
+                    names.insert(name.as_str(), id);
+                }
+            }
+        }
+        self.refresh_indexes();
+    }
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.dump())
     }
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::io::Read;
+use serde::{Serialize, Deserialize};
+use slotmap::SecondaryMap;
+use uuid::Uuid;
+use crate::EntityId;
+
+pub trait Relation : 'static {
+    fn type_id() -> Uuid;
+}
+
+#[derive(Default, Clone)]
+pub(crate) struct RelationStorage {
+    targets:SecondaryMap<EntityId, Vec<EntityId>>,
+    sources:SecondaryMap<EntityId, Vec<EntityId>>
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializableRelation {
+    edges:Vec<(EntityId, EntityId)>
+}
+
+fn detach(map:&mut SecondaryMap<EntityId, Vec<EntityId>>, key:EntityId, value:EntityId) {
+    if let Some(values) = map.get_mut(key) {
+        values.retain(|other| *other != value);
+        if values.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+impl RelationStorage {
+    pub(crate) fn relate(&mut self, source:EntityId, target:EntityId) {
+        if self.contains(source, target) {
+            return;
+        }
+        match self.targets.get_mut(source) {
+            Some(targets) => targets.push(target),
+            None => {
+                self.targets.insert(source, vec![target]);
+            }
+        }
+        match self.sources.get_mut(target) {
+            Some(sources) => sources.push(source),
+            None => {
+                self.sources.insert(target, vec![source]);
+            }
+        }
+    }
+
+    pub(crate) fn unrelate(&mut self, source:EntityId, target:EntityId) -> bool {
+        if !self.contains(source, target) {
+            return false;
+        }
+        detach(&mut self.targets, source, target);
+        detach(&mut self.sources, target, source);
+        true
+    }
+
+    pub(crate) fn contains(&self, source:EntityId, target:EntityId) -> bool {
+        self.targets(source).contains(&target)
+    }
+
+    pub(crate) fn targets(&self, source:EntityId) -> &[EntityId] {
+        match self.targets.get(source) {
+            Some(targets) => targets,
+            None => &[]
+        }
+    }
+
+    pub(crate) fn sources(&self, target:EntityId) -> &[EntityId] {
+        match self.sources.get(target) {
+            Some(sources) => sources,
+            None => &[]
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id:EntityId) {
+        if let Some(targets) = self.targets.remove(id) {
+            for target in targets {
+                detach(&mut self.sources, target, id);
+            }
+        }
+        if let Some(sources) = self.sources.remove(id) {
+            for source in sources {
+                detach(&mut self.targets, source, id);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.targets.clear();
+        self.sources.clear();
+    }
+
+    pub(crate) fn serialize(&self, bytes:&mut Vec<u8>) {
+        let mut edges = Vec::new();
+        for (source, targets) in self.targets.iter() {
+            for target in targets.iter() {
+                edges.push((source, *target));
+            }
+        }
+        bincode::serialize_into(bytes, &SerializableRelation { edges }).expect("failed to serialize relation");
+    }
+
+    pub(crate) fn deserialize(&mut self, reader:&mut dyn Read) -> bincode::Result<()> {
+        let relation:SerializableRelation = bincode::deserialize_from(reader)?;
+        self.clear();
+        for (source, target) in relation.edges {
+            self.relate(source, target);
+        }
+        Ok(())
+    }
+}
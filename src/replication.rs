@@ -0,0 +1,234 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use fxhash::{FxHashMap, FxHashSet};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{EntityId, Registry, SerializableComponent};
+
+pub trait Replicate : SerializableComponent {
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationPacket {
+    pub tick:u64,
+    pub spawned:Vec<EntityId>,
+    pub despawned:Vec<EntityId>,
+    pub updated:Vec<(EntityId, Uuid, Vec<u8>)>,
+    pub removed:Vec<(EntityId, Uuid)>,
+    pub disabled:Vec<EntityId>,
+    pub enabled:Vec<EntityId>
+}
+
+impl ReplicationPacket {
+    pub fn is_empty(&self) -> bool {
+        self.spawned.is_empty() && self.despawned.is_empty() && self.updated.is_empty() && self.removed.is_empty() && self.disabled.is_empty() && self.enabled.is_empty()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("failed to serialize ReplicationPacket")
+    }
+
+    pub fn decode(bytes:&[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+pub trait Transport {
+    fn send(&mut self, bytes:Vec<u8>);
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+pub struct ChannelTransport {
+    sender:Sender<Vec<u8>>,
+    receiver:Receiver<Vec<u8>>
+}
+
+impl ChannelTransport {
+    pub fn pair() -> (Self, Self) {
+        let (a_sender, b_receiver) = channel();
+        let (b_sender, a_receiver) = channel();
+        let a = Self {
+            sender:a_sender,
+            receiver:a_receiver
+        };
+        let b = Self {
+            sender:b_sender,
+            receiver:b_receiver
+        };
+        (a, b)
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, bytes:Vec<u8>) {
+        let _ = self.sender.send(bytes);
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Tracks what has been sent to a single client, use one per connection.
+#[derive(Default)]
+pub struct ReplicationServer {
+    tick:u64,
+    sent:FxHashMap<EntityId, FxHashMap<Uuid, Vec<u8>>>,
+    disabled:FxHashSet<EntityId>
+}
+
+impl ReplicationServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.sent.clear();
+        self.disabled.clear();
+    }
+
+    pub fn update(&mut self, registry:&Registry) -> ReplicationPacket {
+        let mut packet = ReplicationPacket {
+            tick:self.tick,
+            ..Default::default()
+        };
+        self.tick += 1;
+
+        let mut current:FxHashMap<EntityId, FxHashMap<Uuid, Vec<u8>>> = FxHashMap::default();
+        for (uuid, storage) in registry.replicated_storages() {
+            for id in registry.iter_all() {
+                if let Some(bytes) = storage.serialize_one(id) {
+                    current.entry(id).or_default().insert(*uuid, bytes);
+                }
+            }
+        }
+
+        for (id, components) in current.iter() {
+            match self.sent.get(id) {
+                Some(sent) => {
+                    for (uuid, bytes) in components.iter() {
+                        if sent.get(uuid) != Some(bytes) {
+                            packet.updated.push((*id, *uuid, bytes.clone()));
+                        }
+                    }
+                    for uuid in sent.keys() {
+                        if !components.contains_key(uuid) {
+                            packet.removed.push((*id, *uuid));
+                        }
+                    }
+                },
+                None => {
+                    packet.spawned.push(*id);
+                    for (uuid, bytes) in components.iter() {
+                        packet.updated.push((*id, *uuid, bytes.clone()));
+                    }
+                }
+            }
+        }
+
+        for id in self.sent.keys() {
+            if !current.contains_key(id) {
+                packet.despawned.push(*id);
+            }
+        }
+
+        let disabled:FxHashSet<EntityId> = current.keys().copied().filter(|id| !registry.is_enabled(*id)).collect();
+        packet.disabled.extend(disabled.difference(&self.disabled).copied());
+        packet.enabled.extend(self.disabled.difference(&disabled).filter(|id| current.contains_key(id)).copied());
+
+        self.sent = current;
+        self.disabled = disabled;
+        packet
+    }
+
+    pub fn send<T:Transport>(&mut self, registry:&Registry, transport:&mut T) {
+        let packet = self.update(registry);
+        if !packet.is_empty() {
+            transport.send(packet.encode());
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ReplicationClient {
+    tick:u64,
+    entities:FxHashMap<EntityId, EntityId>
+}
+
+impl ReplicationClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn local(&self, server:EntityId) -> Option<EntityId> {
+        self.entities.get(&server).copied()
+    }
+
+    /// Applies everything in `packet` that decodes, malformed components are skipped and the
+    /// first of their errors is returned once the rest of the packet was applied.
+    pub fn apply(&mut self, registry:&mut Registry, packet:&ReplicationPacket) -> bincode::Result<()> {
+        let mut result = Ok(());
+        self.tick = packet.tick;
+        for id in packet.despawned.iter() {
+            if let Some(local) = self.entities.remove(id) {
+                registry.despawn(local);
+            }
+        }
+
+        for id in packet.spawned.iter() {
+            if !self.entities.contains_key(id) {
+                let local = registry.spawn().id();
+                self.entities.insert(*id, local);
+            }
+        }
+
+        for id in packet.disabled.iter() {
+            if let Some(local) = self.entities.get(id) {
+                registry.disable(*local);
+            }
+        }
+
+        for id in packet.enabled.iter() {
+            if let Some(local) = self.entities.get(id) {
+                registry.enable(*local);
+            }
+        }
+
+        for (id, uuid, bytes) in packet.updated.iter() {
+            if let Some(local) = self.entities.get(id) {
+                if let Err(err) = registry.set_component_bytes(*local, *uuid, bytes) {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+
+        for (id, uuid) in packet.removed.iter() {
+            if let Some(local) = self.entities.get(id) {
+                if let Some(storage) = registry.storage_by_id_mut(uuid) {
+                    storage.remove(*local);
+                    storage.touch(*local);
+                }
+            }
+        }
+        result
+    }
+
+    /// Applies all received packets, returns how many were applied without errors.
+    pub fn receive<T:Transport>(&mut self, registry:&mut Registry, transport:&mut T) -> usize {
+        let mut applied = 0;
+        while let Some(bytes) = transport.receive() {
+            if let Some(packet) = ReplicationPacket::decode(&bytes) {
+                if self.apply(registry, &packet).is_ok() {
+                    applied += 1;
+                }
+            }
+        }
+
+        applied
+    }
+}
@@ -0,0 +1,44 @@
+use std::rc::Rc;
+use fxhash::{FxHashMap, FxHashSet};
+use uuid::Uuid;
+use crate::{AttachError, EntityId, Registry};
+
+type InsertFn = Rc<dyn Fn(&mut Registry, EntityId) -> Result<(), AttachError>>;
+
+/// A component that has to be present whenever the owning component is attached.
+#[derive(Clone)]
+pub(crate) struct Required {
+    pub(crate) component:Uuid,
+    /// Inserts the component, `None` inserts the default value of the storage.
+    pub(crate) insert:Option<InsertFn>
+}
+
+impl Required {
+    pub(crate) fn new(component:Uuid, insert:Option<InsertFn>) -> Self {
+        Self {
+            component,
+            insert
+        }
+    }
+}
+
+/// Returns true if `start` is `target` or requires it, directly or through other requirements.
+/// Making `target` require `start` would then form a cycle.
+pub(crate) fn reaches(required:&FxHashMap<Uuid, Vec<Required>>, start:Uuid, target:Uuid) -> bool {
+    if start == target {
+        return true;
+    }
+    let mut visited = FxHashSet::default();
+    let mut stack = vec![start];
+    while let Some(component) = stack.pop() {
+        for next in required.get(&component).into_iter().flatten() {
+            if next.component == target {
+                return true;
+            }
+            if visited.insert(next.component) {
+                stack.push(next.component);
+            }
+        }
+    }
+    false
+}
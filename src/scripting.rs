@@ -0,0 +1,222 @@
+use std::{cell::RefCell, mem::{swap, take}, rc::Rc};
+use rhai::{Array, Dynamic, Engine, EvalAltResult};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::{EntityId, Registry};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Exposes a `Registry` to Rhai scripts, components are addressed by type name or UUID.
+pub struct Scripting {
+    engine:Engine,
+    registry:Rc<RefCell<Registry>>,
+    errors:Rc<RefCell<Vec<String>>>
+}
+
+/// Lends the registry to the scripts and gives it back when dropped, also when a script panics.
+struct Lend<'a> {
+    shared:&'a RefCell<Registry>,
+    registry:&'a mut Registry
+}
+
+impl<'a> Lend<'a> {
+    fn new(shared:&'a RefCell<Registry>, registry:&'a mut Registry) -> Self {
+        swap(&mut *shared.borrow_mut(), registry);
+        Self { shared, registry }
+    }
+}
+
+impl Drop for Lend<'_> {
+    fn drop(&mut self) {
+        swap(&mut *self.shared.borrow_mut(), self.registry);
+    }
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn component(registry:&Registry, name:&str) -> ScriptResult<Uuid> {
+    match registry.component_uuid(name) {
+        Some(id) => Ok(id),
+        None => Err(format!("component {} not registered", name).into())
+    }
+}
+
+fn to_dynamic(value:Value) -> ScriptResult<Dynamic> {
+    rhai::serde::to_dynamic(value)
+}
+
+fn to_value(value:Dynamic) -> ScriptResult<Value> {
+    rhai::serde::from_dynamic(&value)
+}
+
+fn field_of(value:Value, field:&str) -> Value {
+    match value {
+        Value::Object(mut object) => object.remove(field).unwrap_or_default(),
+        _ => Value::Null
+    }
+}
+
+fn set_field(registry:&mut Registry, id:EntityId, name:&str, field:&str, value:Dynamic) -> ScriptResult<()> {
+    let uuid = component(registry, name)?;
+    let mut current = match registry.component_dyn(id, uuid) {
+        Some(Value::Object(object)) => object,
+        _ => return Err(format!("entity has no {} component", name).into())
+    };
+    current.insert(field.to_string(), to_value(value)?);
+    registry.set_component_dyn(id, uuid, Value::Object(current)).map_err(|err| err.to_string().into())
+}
+
+impl Scripting {
+    pub fn new() -> Self {
+        let registry = Rc::new(RefCell::new(Registry::new()));
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.register_type_with_name::<EntityId>("Entity");
+        engine.register_fn("to_string", |id:&mut EntityId| format!("{:?}", id));
+        engine.register_fn("==", |a:EntityId, b:EntityId| a == b);
+        engine.register_fn("!=", |a:EntityId, b:EntityId| a != b);
+
+        let r = registry.clone();
+        engine.register_fn("spawn_entity", move || r.borrow_mut().spawn().id());
+
+        let r = registry.clone();
+        engine.register_fn("despawn_entity", move |id:EntityId| r.borrow_mut().despawn(id));
+
+        let r = registry.clone();
+        engine.register_fn("exists", move |id:EntityId| r.borrow().contains(id));
+
+        let r = registry.clone();
+        engine.register_fn("attach", move |id:EntityId, name:&str| -> ScriptResult<()> {
+            let mut registry = r.borrow_mut();
+            let uuid = component(&registry, name)?;
+            let value = registry.component_default_dyn(uuid).unwrap_or_default();
+            registry.set_component_dyn(id, uuid, value).map_err(|err| err.to_string().into())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("attach", move |id:EntityId, name:&str, value:Dynamic| -> ScriptResult<()> {
+            let mut registry = r.borrow_mut();
+            let uuid = component(&registry, name)?;
+            registry.set_component_dyn(id, uuid, to_value(value)?).map_err(|err| err.to_string().into())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("detach", move |id:EntityId, name:&str| -> ScriptResult<()> {
+            let mut registry = r.borrow_mut();
+            let uuid = component(&registry, name)?;
+            registry.component_detach_dyn(id, uuid);
+            Ok(())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("has", move |id:EntityId, name:&str| -> ScriptResult<bool> {
+            let registry = r.borrow();
+            let uuid = component(&registry, name)?;
+            Ok(registry.component_has_dyn(id, uuid))
+        });
+
+        let r = registry.clone();
+        engine.register_fn("get", move |id:EntityId, name:&str| -> ScriptResult<Dynamic> {
+            let registry = r.borrow();
+            let uuid = component(&registry, name)?;
+            match registry.component_dyn(id, uuid) {
+                Some(value) => to_dynamic(value),
+                None => Ok(Dynamic::UNIT)
+            }
+        });
+
+        let r = registry.clone();
+        engine.register_fn("get", move |id:EntityId, name:&str, field_name:&str| -> ScriptResult<Dynamic> {
+            let registry = r.borrow();
+            let uuid = component(&registry, name)?;
+            match registry.component_dyn(id, uuid) {
+                Some(value) => to_dynamic(field_of(value, field_name)),
+                None => Ok(Dynamic::UNIT)
+            }
+        });
+
+        let r = registry.clone();
+        engine.register_fn("set", move |id:EntityId, name:&str, value:Dynamic| -> ScriptResult<()> {
+            let mut registry = r.borrow_mut();
+            let uuid = component(&registry, name)?;
+            registry.set_component_dyn(id, uuid, to_value(value)?).map_err(|err| err.to_string().into())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("set", move |id:EntityId, name:&str, field_name:&str, value:Dynamic| -> ScriptResult<()> {
+            set_field(&mut r.borrow_mut(), id, name, field_name, value)
+        });
+
+        let r = registry.clone();
+        engine.register_fn("query", move |names:Array| -> ScriptResult<Array> {
+            let registry = r.borrow();
+            let mut components = Vec::new();
+            for name in names {
+                let name = name.into_immutable_string()?;
+                components.push(component(&registry, &name)?);
+            }
+            Ok(registry.iter_with(&components).map(Dynamic::from).collect())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("defer_despawn_entity", move |id:EntityId| {
+            r.borrow().push(move |registry| registry.despawn(id));
+        });
+
+        let r = registry.clone();
+        let e = errors.clone();
+        engine.register_fn("defer_attach", move |id:EntityId, name:&str, value:Dynamic| -> ScriptResult<()> {
+            let registry = r.borrow();
+            let uuid = component(&registry, name)?;
+            let value = to_value(value)?;
+            let errors = e.clone();
+            let name = name.to_string();
+            registry.push(move |registry| {
+                if let Err(err) = registry.set_component_dyn(id, uuid, value.clone()) {
+                    errors.borrow_mut().push(format!("deferred attach of {} failed: {}", name, err));
+                }
+            });
+            Ok(())
+        });
+
+        let r = registry.clone();
+        engine.register_fn("defer_detach", move |id:EntityId, name:&str| -> ScriptResult<()> {
+            let registry = r.borrow();
+            let uuid = component(&registry, name)?;
+            registry.push(move |registry| {
+                registry.component_detach_dyn(id, uuid);
+            });
+            Ok(())
+        });
+
+        Self {
+            engine,
+            registry,
+            errors
+        }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Runs `script` against `registry`. Deferred commands run on the next `Registry::execute`,
+    /// their failures are kept for `take_errors`.
+    pub fn run(&self, registry:&mut Registry, script:&str) -> ScriptResult<Dynamic> {
+        let _lend = Lend::new(&self.registry, registry);
+        self.engine.eval::<Dynamic>(script)
+    }
+
+    /// Takes the failures of deferred attaches collected since the last call.
+    pub fn take_errors(&self) -> Vec<String> {
+        take(&mut *self.errors.borrow_mut())
+    }
+}
@@ -0,0 +1,250 @@
+use std::{any::Any, marker::PhantomData, rc::Rc};
+use fxhash::FxHashMap;
+use slotmap::SecondaryMap;
+use uuid::Uuid;
+use crate::{Component, ComponentIndex, EntityId, Storage};
+
+pub struct SpatialHandle<T> {
+    pub(crate) index:usize,
+    marker:PhantomData<T>
+}
+
+impl<T> SpatialHandle<T> {
+    pub(crate) fn new(index:usize) -> Self {
+        Self {
+            index,
+            marker:PhantomData
+        }
+    }
+}
+
+impl<T> Clone for SpatialHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SpatialHandle<T> {
+}
+
+type PositionFn<T> = Rc<dyn Fn(&T) -> [f32; 2]>;
+
+/// Uniform grid over the 2d positions returned by the accessor.
+pub struct SpatialIndex<T> {
+    position_fn:PositionFn<T>,
+    cell_size:f32,
+    cells:FxHashMap<(i32, i32), Vec<EntityId>>,
+    positions:SecondaryMap<EntityId, [f32; 2]>
+}
+
+fn distance_squared(a:[f32; 2], b:[f32; 2]) -> f32 {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    dx * dx + dy * dy
+}
+
+fn chebyshev(a:(i32, i32), b:(i32, i32)) -> i64 {
+    (i64::from(a.0) - i64::from(b.0)).abs().max((i64::from(a.1) - i64::from(b.1)).abs())
+}
+
+/// Cells at exactly `ring` cells from `center`, skipping those outside the `i32` range.
+fn ring_cells(center:(i32, i32), ring:i64) -> Vec<(i32, i32)> {
+    if ring == 0 {
+        return vec![center];
+    }
+    let (cx, cy) = (i64::from(center.0), i64::from(center.1));
+    let rows = (-ring..=ring).flat_map(|dx| [(cx + dx, cy - ring), (cx + dx, cy + ring)]);
+    let columns = (1 - ring..ring).flat_map(|dy| [(cx - ring, cy + dy), (cx + ring, cy + dy)]);
+    rows.chain(columns).filter_map(|(x, y)| Some((i32::try_from(x).ok()?, i32::try_from(y).ok()?))).collect()
+}
+
+impl<T:Component> SpatialIndex<T> {
+    pub(crate) fn new<F:Fn(&T) -> [f32; 2] + 'static>(cell_size:f32, position_fn:F) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        Self {
+            position_fn:Rc::new(position_fn),
+            cell_size,
+            cells:FxHashMap::default(),
+            positions:SecondaryMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, id:EntityId) -> Option<[f32; 2]> {
+        self.positions.get(id).copied()
+    }
+
+    pub fn within_aabb(&self, min:[f32; 2], max:[f32; 2]) -> Vec<EntityId> {
+        let (min_x, min_y) = self.cell(min);
+        let (max_x, max_y) = self.cell(max);
+        let inside = |p:[f32; 2]| p[0] >= min[0] && p[0] <= max[0] && p[1] >= min[1] && p[1] <= max[1];
+        let mut found = Vec::new();
+        let area = (i64::from(max_x) - i64::from(min_x) + 1).max(0).saturating_mul((i64::from(max_y) - i64::from(min_y) + 1).max(0));
+        if area > self.cells.len() as i64 {
+            // fewer occupied cells than cells in the box, so visit those instead
+            for (cell, ids) in self.cells.iter() {
+                if (min_x..=max_x).contains(&cell.0) && (min_y..=max_y).contains(&cell.1) {
+                    found.extend(ids.iter().filter(|id| inside(self.positions[**id])));
+                }
+            }
+            return found;
+        }
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(ids) = self.cells.get(&(x, y)) {
+                    found.extend(ids.iter().filter(|id| inside(self.positions[**id])));
+                }
+            }
+        }
+        found
+    }
+
+    pub fn within_radius(&self, center:[f32; 2], radius:f32) -> Vec<EntityId> {
+        let min = [center[0] - radius, center[1] - radius];
+        let max = [center[0] + radius, center[1] + radius];
+        let mut found = self.within_aabb(min, max);
+        found.retain(|id| distance_squared(self.positions[*id], center) <= radius * radius);
+        found
+    }
+
+    /// Returns up to `k` entities ordered by distance to `center`.
+    pub fn nearest_k(&self, center:[f32; 2], k:usize) -> Vec<EntityId> {
+        if k == 0 || self.positions.is_empty() {
+            return Vec::new();
+        }
+        let center_cell = self.cell(center);
+        let extent = self.cells.keys().map(|cell| chebyshev(*cell, center_cell)).max().unwrap_or(0);
+        let mut candidates:Vec<(f32, EntityId)> = Vec::new();
+        for ring in 0..=extent {
+            if ring.saturating_mul(8) > self.cells.len() as i64 {
+                // the remaining rings have more cells than are occupied, visit the occupied ones at once
+                for (_, ids) in self.cells.iter().filter(|(cell, _)| chebyshev(**cell, center_cell) >= ring) {
+                    candidates.extend(self.distances(ids, center));
+                }
+                break;
+            }
+            for cell in ring_cells(center_cell, ring) {
+                if let Some(ids) = self.cells.get(&cell) {
+                    candidates.extend(self.distances(ids, center));
+                }
+            }
+            if candidates.len() >= k {
+                candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let reach = ring as f32 * self.cell_size;
+                if candidates[k - 1].0 <= reach * reach {
+                    break;
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        candidates.into_iter().take(k).map(|(_, id)| id).collect()
+    }
+
+    fn distances<'a>(&'a self, ids:&'a [EntityId], center:[f32; 2]) -> impl Iterator<Item = (f32, EntityId)> + 'a {
+        ids.iter().map(move |id| (distance_squared(self.positions[*id], center), *id))
+    }
+
+    /// Clamped to the `i32` range, NaN ends up in cell 0.
+    fn cell(&self, p:[f32; 2]) -> (i32, i32) {
+        let clamp = |v:f32| (v / self.cell_size).floor().clamp(i32::MIN as f32, i32::MAX as f32) as i32;
+        (clamp(p[0]), clamp(p[1]))
+    }
+
+    fn insert_position(&mut self, id:EntityId, position:[f32; 2]) {
+        self.remove_entity(id);
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push(id);
+        self.positions.insert(id, position);
+    }
+
+    fn remove_entity(&mut self, id:EntityId) {
+        if let Some(position) = self.positions.remove(id) {
+            let cell = self.cell(position);
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+}
+
+impl<T:Component> ComponentIndex for SpatialIndex<T> {
+    fn component(&self) -> Uuid {
+        T::type_id()
+    }
+
+    fn conflicts(&self, _id:EntityId, _component:&dyn Any) -> bool {
+        false
+    }
+
+    fn holder(&self, _component:&dyn Any) -> Option<EntityId> {
+        None
+    }
+
+    fn insert(&mut self, id:EntityId, component:&dyn Any) {
+        if let Some(component) = component.downcast_ref::<T>() {
+            let position = (self.position_fn)(component);
+            self.insert_position(id, position);
+        }
+    }
+
+    fn refresh(&mut self, storage:&Storage, id:EntityId) -> bool {
+        let position = match storage.typed::<T>().get(id) {
+            Some(cell) => match cell.try_borrow() {
+                Ok(component) => Some((self.position_fn)(&component)),
+                Err(_) => return false
+            },
+            None => None
+        };
+        match position {
+            Some(position) => self.insert_position(id, position),
+            None => self.remove_entity(id)
+        }
+        true
+    }
+
+    fn remove(&mut self, id:EntityId) {
+        self.remove_entity(id);
+    }
+
+    fn unique(&self) -> bool {
+        false
+    }
+
+    fn holder_of(&self, _storage:&Storage, _id:EntityId, _except:EntityId) -> Option<EntityId> {
+        None
+    }
+
+    fn rebuild(&mut self, storage:&Storage) {
+        self.cells.clear();
+        self.positions.clear();
+        for (id, cell) in storage.typed::<T>().iter() {
+            if let Ok(component) = cell.try_borrow() {
+                let position = (self.position_fn)(&component);
+                self.insert_position(id, position);
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn ComponentIndex> {
+        Box::new(Self {
+            position_fn:self.position_fn.clone(),
+            cell_size:self.cell_size,
+            cells:self.cells.clone(),
+            positions:self.positions.clone()
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt::Display;
+use fxhash::{FxHashMap, FxHashSet};
+use rusqlite::{params, Connection, types::Value as SqlValue};
+use serde_json::{Map, Number, Value};
+use slotmap::{Key, KeyData};
+use uuid::Uuid;
+use crate::{EntityId, ReflectError, Registry, Storage};
+
+#[derive(Debug)]
+pub enum SqliteError {
+    Sqlite(rusqlite::Error),
+    Reflect(ReflectError)
+}
+
+impl Display for SqliteError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqliteError::Sqlite(err) => write!(f, "sqlite error: {}", err),
+            SqliteError::Reflect(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for SqliteError {
+}
+
+impl From<rusqlite::Error> for SqliteError {
+    fn from(err:rusqlite::Error) -> Self {
+        SqliteError::Sqlite(err)
+    }
+}
+
+impl From<ReflectError> for SqliteError {
+    fn from(err:ReflectError) -> Self {
+        SqliteError::Reflect(err)
+    }
+}
+
+/// How a serde value is stored, derived from the component's default value and widened to
+/// `Json` when a stored value has another shape.
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    Integer,
+    Real,
+    Bool,
+    Text,
+    Json
+}
+
+impl Column {
+    fn of(value:&Value) -> Self {
+        match value {
+            Value::Number(number) if number.is_f64() => Column::Real,
+            Value::Number(_) => Column::Integer,
+            Value::Bool(_) => Column::Bool,
+            Value::String(_) => Column::Text,
+            _ => Column::Json
+        }
+    }
+
+    fn fits(self, value:&Value) -> bool {
+        self == Column::Json || value.is_null() || Column::of(value) == self
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Column::Integer => "integer",
+            Column::Real => "real",
+            Column::Bool => "bool",
+            Column::Text => "text",
+            Column::Json => "json"
+        }
+    }
+
+    fn from_name(name:&str) -> Self {
+        match name {
+            "integer" => Column::Integer,
+            "real" => Column::Real,
+            "bool" => Column::Bool,
+            "text" => Column::Text,
+            _ => Column::Json
+        }
+    }
+
+    fn sql_type(self) -> &'static str {
+        match self {
+            Column::Integer | Column::Bool => "INTEGER",
+            Column::Real => "REAL",
+            Column::Text | Column::Json => "TEXT"
+        }
+    }
+
+    fn encode(self, value:&Value) -> SqlValue {
+        match (self, value) {
+            (_, Value::Null) => SqlValue::Null,
+            (Column::Integer, Value::Number(number)) => match number.as_i64() {
+                Some(int) => SqlValue::Integer(int),
+                None => SqlValue::Real(number.as_f64().unwrap_or_default())
+            },
+            (Column::Real, Value::Number(number)) => SqlValue::Real(number.as_f64().unwrap_or_default()),
+            (Column::Bool, Value::Bool(bool)) => SqlValue::Integer(i64::from(*bool)),
+            (Column::Text, Value::String(string)) => SqlValue::Text(string.clone()),
+            (_, value) => SqlValue::Text(value.to_string())
+        }
+    }
+
+    /// Text of any column but `Text` is parsed as json first, falling back to a plain string.
+    fn decode(self, value:SqlValue) -> Value {
+        match (self, value) {
+            (Column::Bool, SqlValue::Integer(int)) => Value::Bool(int != 0),
+            (_, SqlValue::Integer(int)) => Value::Number(int.into()),
+            (_, SqlValue::Real(real)) => Number::from_f64(real).map(Value::Number).unwrap_or_default(),
+            (Column::Text, SqlValue::Text(text)) => Value::String(text),
+            (_, SqlValue::Text(text)) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            (_, SqlValue::Null | SqlValue::Blob(_)) => Value::Null
+        }
+    }
+}
+
+/// Columns of a component table. Structs get a column per field unless a stored value has fields
+/// the default lacks, anything else is packed into a single `value` column.
+struct Layout {
+    packed:bool,
+    columns:Vec<(String, Column)>
+}
+
+impl Layout {
+    fn new<'a>(default:&Value, values:impl Iterator<Item = &'a Value> + Clone) -> Self {
+        let packed = match default {
+            Value::Object(defaults) if !defaults.is_empty() => !values.clone().all(|value| {
+                matches!(value, Value::Object(fields) if fields.keys().all(|name| defaults.contains_key(name)))
+            }),
+            _ => true
+        };
+        let mut columns = match default {
+            Value::Object(defaults) if !packed => defaults.iter().map(|(name, value)| (name.clone(), Column::of(value))).collect(),
+            value => vec![("value".to_string(), Column::of(value))]
+        };
+        for value in values {
+            for (name, column) in columns.iter_mut() {
+                let field = if packed { Some(value) } else { value.get(name.as_str()) };
+                if field.is_some_and(|field| !column.fits(field)) {
+                    *column = Column::Json;
+                }
+            }
+        }
+        Self { packed, columns }
+    }
+
+    fn encode(&self, value:&Value) -> Vec<SqlValue> {
+        if self.packed {
+            return vec![self.columns[0].1.encode(value)];
+        }
+        self.columns.iter().map(|(name, column)| column.encode(value.get(name.as_str()).unwrap_or(&Value::Null))).collect()
+    }
+
+    fn describe(&self) -> String {
+        let columns:Vec<(&str, &str)> = self.columns.iter().map(|(name, column)| (name.as_str(), column.name())).collect();
+        serde_json::to_string(&columns).unwrap_or_default()
+    }
+
+    fn column(&self, name:&str) -> Column {
+        self.columns.iter().find(|(column, _)| column == name).map(|(_, column)| *column).unwrap_or(Column::Json)
+    }
+}
+
+fn quote(name:&str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn table_name(name:&str, used:&mut FxHashSet<String>) -> String {
+    let base:String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let mut table = base.clone();
+    let mut n = 1;
+    while !used.insert(table.to_lowercase()) {
+        n += 1;
+        table = format!("{}_{}", base, n);
+    }
+    table
+}
+
+fn to_sql_id(id:EntityId) -> i64 {
+    id.data().as_ffi() as i64
+}
+
+fn from_sql_id(id:i64) -> EntityId {
+    KeyData::from_ffi(id as u64).into()
+}
+
+impl Registry {
+    /// Writes all entities to an `entities` table and every serialized component to a table named after it,
+    /// existing tables of the same name are replaced. `components` maps the tables back to component UUIDs.
+    pub fn export_sqlite(&self, connection:&Connection) -> Result<(), SqliteError> {
+        let transaction = connection.unchecked_transaction()?;
+        let mut used:FxHashSet<String> = ["entities", "components"].iter().map(|name| name.to_string()).collect();
+        if let Ok(mut statement) = transaction.prepare("SELECT table_name FROM components") {
+            let tables:Vec<String> = statement.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+            for table in tables {
+                transaction.execute(&format!("DROP TABLE IF EXISTS {}", quote(&table)), [])?;
+            }
+        }
+        transaction.execute_batch("
+            DROP TABLE IF EXISTS entities;
+            DROP TABLE IF EXISTS components;
+            CREATE TABLE entities (id INTEGER PRIMARY KEY, enabled INTEGER NOT NULL);
+            CREATE TABLE components (uuid TEXT PRIMARY KEY, name TEXT NOT NULL, table_name TEXT NOT NULL, packed INTEGER NOT NULL, columns TEXT NOT NULL);
+        ")?;
+        {
+            let mut insert = transaction.prepare("INSERT INTO entities (id, enabled) VALUES (?1, ?2)")?;
+            for id in self.iter_all() {
+                insert.execute(params![to_sql_id(id), self.is_enabled(id)])?;
+            }
+        }
+        for (uuid, storage) in self.storages().filter(|(_, storage)| storage.serialized) {
+            let table = table_name(&storage.name, &mut used);
+            let values:Vec<(EntityId, Value)> = self.iter_all().filter_map(|id| Some((id, storage.reflect_get(id)?))).collect();
+            let layout = Layout::new(&storage.reflect_default(), values.iter().map(|(_, value)| value));
+            let mut definitions = vec!["entity INTEGER PRIMARY KEY REFERENCES entities(id)".to_string()];
+            definitions.extend(layout.columns.iter().map(|(name, column)| format!("{} {}", quote(name), column.sql_type())));
+            transaction.execute(&format!("DROP TABLE IF EXISTS {}", quote(&table)), [])?;
+            transaction.execute(&format!("CREATE TABLE {} ({})", quote(&table), definitions.join(", ")), [])?;
+            transaction.execute("INSERT INTO components (uuid, name, table_name, packed, columns) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![uuid.to_string(), storage.name, table, layout.packed, layout.describe()])?;
+
+            let mut names = vec!["entity".to_string()];
+            names.extend(layout.columns.iter().map(|(name, _)| quote(name)));
+            let placeholders:Vec<String> = (1..=names.len()).map(|n| format!("?{}", n)).collect();
+            let mut insert = transaction.prepare(&format!("INSERT INTO {} ({}) VALUES ({})", quote(&table), names.join(", "), placeholders.join(", ")))?;
+            for (id, value) in values {
+                let mut row = vec![SqlValue::Integer(to_sql_id(id))];
+                row.extend(layout.encode(&value));
+                insert.execute(rusqlite::params_from_iter(row))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Replaces the contents of the registry with a database written by `export_sqlite`, tables of
+    /// unregistered components are skipped and fields missing from a table keep their default value.
+    /// The whole database is read and checked first, on error the registry is left untouched.
+    pub fn import_sqlite(&mut self, connection:&Connection) -> Result<(), SqliteError> {
+        let entities:Vec<(i64, bool)> = connection.prepare("SELECT id, enabled FROM entities")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        let tables:Vec<(String, String, bool, String)> = connection.prepare("SELECT uuid, table_name, packed, columns FROM components")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<Result<_, _>>()?;
+        let mut components = Vec::new();
+        for (uuid, table, packed, columns) in tables {
+            let Ok(uuid) = Uuid::parse_str(&uuid) else {
+                continue;
+            };
+            let Some(default) = self.storages().find(|(id, storage)| **id == uuid && storage.serialized).map(|(_, storage)| storage.reflect_default()) else {
+                continue;
+            };
+            let columns:Vec<(String, String)> = serde_json::from_str(&columns).unwrap_or_default();
+            let layout = Layout {
+                packed,
+                columns:columns.into_iter().map(|(name, column)| (name, Column::from_name(&column))).collect()
+            };
+            let mut statement = connection.prepare(&format!("SELECT * FROM {}", quote(&table)))?;
+            let names:Vec<String> = statement.column_names().into_iter().map(str::to_string).collect();
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                let mut id = None;
+                let mut fields = Map::new();
+                for (n, name) in names.iter().enumerate() {
+                    let value:SqlValue = row.get(n)?;
+                    if name == "entity" {
+                        if let SqlValue::Integer(int) = value {
+                            id = Some(from_sql_id(int));
+                        }
+                        continue;
+                    }
+                    fields.insert(name.clone(), layout.column(name).decode(value));
+                }
+                let Some(id) = id else {
+                    continue;
+                };
+                let value = match &default {
+                    Value::Object(defaults) if !packed => {
+                        let mut value = defaults.clone();
+                        value.extend(fields.into_iter().filter(|(name, _)| defaults.contains_key(name)));
+                        Value::Object(value)
+                    },
+                    _ => fields.remove("value").unwrap_or_default()
+                };
+                components.push((id, uuid, value));
+            }
+        }
+        self.check_sqlite(&entities, &components)?;
+        self.apply_sqlite(&entities, components)
+    }
+
+    /// Fails like `apply_sqlite` would, without changing the registry. Values are decoded into
+    /// empty storages, which keep them only for components with unique keys or names.
+    fn check_sqlite(&self, entities:&[(i64, bool)], components:&[(EntityId, Uuid, Value)]) -> Result<(), ReflectError> {
+        let ids:FxHashSet<EntityId> = entities.iter().map(|(id, _)| from_sql_id(*id)).collect();
+        let mut scratch:FxHashMap<Uuid, (Storage, Vec<EntityId>)> = FxHashMap::default();
+        for (id, uuid, value) in components {
+            if !ids.contains(id) {
+                return Err(ReflectError::UnknownEntity(*id));
+            }
+            let (storage, held) = match scratch.get_mut(uuid) {
+                Some(scratch) => scratch,
+                None => {
+                    let storage = self.storages().find(|(other, _)| *other == uuid).ok_or(ReflectError::UnknownComponent(*uuid))?.1.scratch();
+                    scratch.entry(*uuid).or_insert((storage, Vec::new()))
+                }
+            };
+            storage.reflect_set(*id, value.clone())?;
+            match self.has_unique(*uuid) {
+                true => held.push(*id),
+                false => storage.remove(*id)
+            }
+        }
+        for (uuid, (storage, held)) in scratch.iter() {
+            if let Some(err) = self.scratch_conflict(*uuid, storage, held) {
+                return Err(ReflectError::Conflict(err));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_sqlite(&mut self, entities:&[(i64, bool)], components:Vec<(EntityId, Uuid, Value)>) -> Result<(), SqliteError> {
+        self.clear();
+        for (id, enabled) in entities {
+            let id = from_sql_id(*id);
+            self.spawn_at(id);
+            if !enabled {
+                self.disable(id);
+            }
+        }
+        for (id, uuid, value) in components {
+            self.set_component_dyn(id, uuid, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::{Display, Formatter, Result};
+use uuid::Uuid;
+use crate::Storage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageStats {
+    pub uuid:Uuid,
+    pub name:String,
+    pub len:usize,
+    pub capacity:usize,
+    pub bytes:usize,
+    pub skipped:usize
+}
+
+impl StorageStats {
+    pub(crate) fn new(uuid:Uuid, storage:&Storage) -> Self {
+        Self {
+            uuid,
+            name:storage.name.clone(),
+            len:storage.len(),
+            capacity:storage.capacity(),
+            bytes:storage.bytes(),
+            skipped:storage.skipped()
+        }
+    }
+}
+
+/// Memory report returned by `Registry::stats`, byte counts cover the registry's own
+/// allocations but not heap memory owned by components.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RegistryStats {
+    pub entities:usize,
+    pub entity_capacity:usize,
+    pub free_entities:usize,
+    pub reserved_entities:usize,
+    pub entity_bytes:usize,
+    pub components:Vec<StorageStats>,
+    pub singletons:Vec<StorageStats>,
+    pub pending_commands:usize
+}
+
+impl RegistryStats {
+    pub fn component(&self, uuid:Uuid) -> Option<&StorageStats> {
+        self.components.iter().find(|stats| stats.uuid == uuid)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.entity_bytes + self.components.iter().chain(self.singletons.iter()).map(|stats| stats.bytes).sum::<usize>()
+    }
+}
+
+impl Display for RegistryStats {
+    fn fmt(&self, f:&mut Formatter<'_>) -> Result {
+        writeln!(f, "Registry {{ bytes: {}, pending commands: {} }}", self.bytes(), self.pending_commands)?;
+        writeln!(f, "Entities: {} (capacity {}, free {}, reserved {}, {} bytes)", self.entities, self.entity_capacity, self.free_entities, self.reserved_entities, self.entity_bytes)?;
+        writeln!(f, "Components:")?;
+        for stats in self.components.iter() {
+            writeln!(f, "  {}: {} (capacity {}, {} bytes, {} skipped)", stats.name, stats.len, stats.capacity, stats.bytes, stats.skipped)?;
+        }
+        writeln!(f, "Singletons:")?;
+        for stats in self.singletons.iter() {
+            writeln!(f, "  {}: {} bytes", stats.name, stats.bytes)?;
+        }
+        Ok(())
+    }
+}
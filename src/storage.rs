@@ -1,126 +1,717 @@
-use std::cell::RefCell;
-use std::io::BufWriter;
-use slotmap::SecondaryMap;
-use crate::EntityId;
-use crate::Component;
+use std::any::{type_name, Any};
+use std::fmt::Debug;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::io::{self, Read, Write};
+use std::mem::{size_of, take};
+use std::num::NonZeroU32;
+use serde_json::Value;
+use slotmap::{Key, SecondaryMap};
+use crate::{bincode_error, Codec, EntityId, ReflectError};
+use crate::{Component, SerializableComponent};
 
-pub struct Storage {
-    pub ptr:*mut (),
-    pub drop_fn:Box<dyn Fn()>,
-    pub serialize_fn:Box<dyn Fn(&mut Vec<u8>)>,
-    pub deserialize_fn:Box<dyn Fn(&[u8])>,
-    pub remove_fn:Box<dyn Fn(EntityId)>,
-    pub clear_fn:Box<dyn Fn()>,
-    pub clone_fn:Box<dyn Fn()->Self>,
-    pub default_fn:Box<dyn Fn(EntityId)>
+type DebugFn = fn(&Storage, EntityId) -> Option<String>;
+type CloneMapFn<T> = fn(&SecondaryMap<EntityId, RefCell<T>>) -> SecondaryMap<EntityId, RefCell<T>>;
+
+/// Size of one slot of a `SecondaryMap<EntityId, RefCell<T>>`.
+pub(crate) fn slot_size<T>() -> usize {
+    size_of::<Option<(NonZeroU32, RefCell<T>)>>()
 }
 
-impl Storage {
-    pub fn new<T:Component>() -> Self {
-        let map:SecondaryMap<EntityId, RefCell<T>> = SecondaryMap::new();
-        let boxed = Box::new(map);
-        let ptr = Box::into_raw(boxed);
-        let f = move || {
-            unsafe {
-                let _ = Box::from_raw(ptr);
-            }
-        };
-        let serialize_fn = move |bytes:&mut Vec<u8>| {
-            unsafe {
-                let map = ptr.as_ref().unwrap();
-                let writer = BufWriter::new(bytes);
-                bincode::serialize_into(writer, map).expect("failed to serialize");
-            }
-        };
-        let deserialize_fn = move |bytes:&[u8]| {
-            unsafe {
-                *ptr.as_mut().unwrap() = bincode::deserialize(bytes).unwrap();
+/// Reallocates `map` so that its capacity only covers the highest occupied index.
+pub(crate) fn shrink<T>(map:&mut SecondaryMap<EntityId, T>) {
+    let highest = map.keys().map(|id| id.data().as_ffi() as u32 as usize).max().unwrap_or(0);
+    let mut shrunk = SecondaryMap::with_capacity(highest);
+    for (id, value) in map.drain() {
+        shrunk.insert(id, value);
+    }
+    *map = shrunk;
+}
+
+/// Removes the component of `id` from `map`, boxed so it can be moved to another storage.
+pub(crate) fn take_boxed<T:'static>(map:&mut SecondaryMap<EntityId, RefCell<T>>, id:EntityId) -> Option<Box<dyn Any>> {
+    map.remove(id).map(|cell| Box::new(cell.into_inner()) as Box<dyn Any>)
+}
+
+/// Inserts a component boxed by `take_boxed`, handing it back if it is not a `T`.
+pub(crate) fn put_boxed<T:'static>(map:&mut SecondaryMap<EntityId, RefCell<T>>, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+    map.insert(id, RefCell::new(*component.downcast::<T>()?));
+    Ok(())
+}
+
+pub(crate) fn short_name<T>() -> String {
+    type_name::<T>().split('<').next().unwrap_or_default().rsplit("::").next().unwrap_or_default().to_string()
+}
+
+/// Operations of a storage that do not depend on the component type.
+pub(crate) trait ErasedStorage {
+    /// The `SecondaryMap<EntityId, RefCell<T>>` holding the components.
+    fn map(&self) -> &dyn Any;
+    fn map_mut(&mut self) -> &mut dyn Any;
+    fn serialized_size(&self) -> u64;
+    fn serialize(&self, writer:&mut dyn Write) -> bincode::Result<()>;
+    fn deserialize(&mut self, reader:&mut dyn Read) -> bincode::Result<()>;
+    fn serialize_one(&self, id:EntityId) -> Option<Vec<u8>>;
+    fn deserialize_one(&mut self, id:EntityId, bytes:&[u8]) -> bincode::Result<()>;
+    fn remove(&mut self, id:EntityId);
+    fn take(&mut self, id:EntityId) -> Option<Box<dyn Any>>;
+    fn put(&mut self, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>>;
+    fn clear(&mut self);
+    fn clone_box(&self) -> Box<dyn ErasedStorage>;
+    #[cfg(feature = "sqlite")]
+    fn clone_empty(&self) -> Box<dyn ErasedStorage>;
+    fn default(&mut self, id:EntityId);
+    /// Inserts the default value, returns false if the component has none.
+    fn insert_default(&mut self, id:EntityId) -> bool;
+    fn has_default(&self) -> bool;
+    fn has(&self, id:EntityId) -> bool;
+    fn len(&self) -> usize;
+    fn capacity(&self) -> usize;
+    fn reserve(&mut self, capacity:usize);
+    fn shrink_to_fit(&mut self);
+    fn slot_size(&self) -> usize;
+    fn reflect_get(&self, id:EntityId) -> Option<Value>;
+    fn reflect_set(&mut self, id:EntityId, value:Value) -> Result<(), ReflectError>;
+    fn reflect_default(&self) -> Value;
+}
+
+struct TypedStorage<T> {
+    map:SecondaryMap<EntityId, RefCell<T>>
+}
+
+impl<T:SerializableComponent> ErasedStorage for TypedStorage<T> {
+    fn map(&self) -> &dyn Any {
+        &self.map
+    }
+
+    fn map_mut(&mut self) -> &mut dyn Any {
+        &mut self.map
+    }
+
+    fn serialized_size(&self) -> u64 {
+        bincode::serialized_size(&self.map).expect("failed to serialize")
+    }
+
+    fn serialize(&self, writer:&mut dyn Write) -> bincode::Result<()> {
+        bincode::serialize_into(writer, &self.map)
+    }
+
+    fn deserialize(&mut self, reader:&mut dyn Read) -> bincode::Result<()> {
+        self.map = bincode::deserialize_from(reader)?;
+        Ok(())
+    }
+
+    fn serialize_one(&self, id:EntityId) -> Option<Vec<u8>> {
+        self.map.get(id).map(|cell| bincode::serialize(cell).expect("failed to serialize"))
+    }
+
+    fn deserialize_one(&mut self, id:EntityId, bytes:&[u8]) -> bincode::Result<()> {
+        let component:T = bincode::deserialize(bytes)?;
+        match self.map.get_mut(id) {
+            Some(cell) => {
+                cell.replace(component);
+            },
+            None => {
+                self.map.insert(id, RefCell::new(component));
             }
-        };
-        let remove_fn = move |id:EntityId| {
-            unsafe {
-                ptr.as_mut().unwrap().remove(id);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id:EntityId) {
+        self.map.remove(id);
+    }
+
+    fn take(&mut self, id:EntityId) -> Option<Box<dyn Any>> {
+        take_boxed(&mut self.map, id)
+    }
+
+    fn put(&mut self, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        put_boxed(&mut self.map, id, component)
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            map:self.map.clone()
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn clone_empty(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            map:SecondaryMap::new()
+        })
+    }
+
+    fn default(&mut self, id:EntityId) {
+        if let Some(v) = self.map.get_mut(id) {
+            v.replace(T::default());
+        }
+    }
+
+    fn insert_default(&mut self, id:EntityId) -> bool {
+        self.map.insert(id, RefCell::new(T::default()));
+        true
+    }
+
+    fn has_default(&self) -> bool {
+        true
+    }
+
+    fn has(&self, id:EntityId) -> bool {
+        self.map.contains_key(id)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    fn reserve(&mut self, capacity:usize) {
+        self.map.set_capacity(capacity);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        shrink(&mut self.map);
+    }
+
+    fn slot_size(&self) -> usize {
+        slot_size::<T>()
+    }
+
+    fn reflect_get(&self, id:EntityId) -> Option<Value> {
+        let component = self.map.get(id)?.try_borrow().ok()?;
+        serde_json::to_value(&*component).ok()
+    }
+
+    fn reflect_set(&mut self, id:EntityId, value:Value) -> Result<(), ReflectError> {
+        let component:T = serde_json::from_value(value).map_err(|err| ReflectError::Invalid(err.to_string()))?;
+        match self.map.get(id) {
+            Some(cell) => {
+                let mut current = cell.try_borrow_mut().map_err(|_| ReflectError::Borrowed)?;
+                *current = component;
+            },
+            None => {
+                self.map.insert(id, RefCell::new(component));
             }
+        }
+        Ok(())
+    }
+
+    fn reflect_default(&self) -> Value {
+        serde_json::to_value(T::default()).unwrap_or_default()
+    }
+}
+
+struct RuntimeStorage<T> {
+    map:SecondaryMap<EntityId, RefCell<T>>,
+    clone_fn:Option<CloneMapFn<T>>
+}
+
+fn clone_map<T:Clone>(map:&SecondaryMap<EntityId, RefCell<T>>) -> SecondaryMap<EntityId, RefCell<T>> {
+    map.clone()
+}
+
+impl<T:Component> ErasedStorage for RuntimeStorage<T> {
+    fn map(&self) -> &dyn Any {
+        &self.map
+    }
+
+    fn map_mut(&mut self) -> &mut dyn Any {
+        &mut self.map
+    }
+
+    fn serialized_size(&self) -> u64 {
+        0
+    }
+
+    fn serialize(&self, _writer:&mut dyn Write) -> bincode::Result<()> {
+        Ok(())
+    }
+
+    fn deserialize(&mut self, _reader:&mut dyn Read) -> bincode::Result<()> {
+        self.map.clear();
+        Ok(())
+    }
+
+    fn serialize_one(&self, _id:EntityId) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn deserialize_one(&mut self, _id:EntityId, _bytes:&[u8]) -> bincode::Result<()> {
+        Ok(())
+    }
+
+    fn remove(&mut self, id:EntityId) {
+        self.map.remove(id);
+    }
+
+    fn take(&mut self, id:EntityId) -> Option<Box<dyn Any>> {
+        take_boxed(&mut self.map, id)
+    }
+
+    fn put(&mut self, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        put_boxed(&mut self.map, id, component)
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            map:self.clone_fn.map(|clone_fn| clone_fn(&self.map)).unwrap_or_default(),
+            clone_fn:self.clone_fn
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn clone_empty(&self) -> Box<dyn ErasedStorage> {
+        Box::new(Self {
+            map:SecondaryMap::new(),
+            clone_fn:self.clone_fn
+        })
+    }
+
+    fn default(&mut self, _id:EntityId) {
+    }
+
+    fn insert_default(&mut self, _id:EntityId) -> bool {
+        false
+    }
+
+    fn has_default(&self) -> bool {
+        false
+    }
+
+    fn has(&self, id:EntityId) -> bool {
+        self.map.contains_key(id)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    fn reserve(&mut self, capacity:usize) {
+        self.map.set_capacity(capacity);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        shrink(&mut self.map);
+    }
+
+    fn slot_size(&self) -> usize {
+        slot_size::<T>()
+    }
+
+    fn reflect_get(&self, _id:EntityId) -> Option<Value> {
+        None
+    }
+
+    fn reflect_set(&mut self, _id:EntityId, _value:Value) -> Result<(), ReflectError> {
+        Err(ReflectError::Invalid("runtime components can not be reflected".to_string()))
+    }
+
+    fn reflect_default(&self) -> Value {
+        Value::Null
+    }
+}
+
+/// Section left compressed by `Registry::deserialize_from`, with the empty storage it inflates into.
+struct Packed {
+    codec:Codec,
+    bytes:Vec<u8>,
+    empty:Box<dyn ErasedStorage>
+}
+
+pub struct Storage {
+    pub(crate) name:String,
+    inner:OnceCell<Box<dyn ErasedStorage>>,
+    packed:RefCell<Option<Packed>>,
+    pub(crate) debug_fn:Option<DebugFn>,
+    pub(crate) replicated:bool,
+    pub(crate) tracked:bool,
+    pub(crate) touched:RefCell<SecondaryMap<EntityId, ()>>,
+    pub(crate) journaled:bool,
+    pub(crate) changed:RefCell<SecondaryMap<EntityId, ()>>,
+    pub(crate) serialized:bool,
+    pub(crate) skipped:Cell<usize>,
+    order:Vec<EntityId>,
+    ordered:bool
+}
+
+impl Storage {
+    pub(crate) fn from_erased(name:String, inner:Box<dyn ErasedStorage>, serialized:bool) -> Self {
+        Self {
+            name,
+            inner:OnceCell::from(inner),
+            packed:RefCell::new(None),
+            debug_fn:None,
+            replicated:false,
+            tracked:false,
+            touched:RefCell::new(SecondaryMap::new()),
+            journaled:false,
+            changed:RefCell::new(SecondaryMap::new()),
+            serialized,
+            skipped:Cell::new(0),
+            order:Vec::new(),
+            ordered:false
+        }
+    }
+
+    pub fn new<T:SerializableComponent>() -> Self {
+        let inner = TypedStorage::<T> {
+            map:SecondaryMap::new()
         };
-        let clear_fn = move || {
-            unsafe {
-                ptr.as_mut().unwrap().clear();
-            }
+        Self::from_erased(short_name::<T>(), Box::new(inner), true)
+    }
+
+    /// Storage for components that are never saved, the storage is left empty by `deserialize` and `clone`.
+    pub fn new_runtime<T:Component>() -> Self {
+        let inner = RuntimeStorage::<T> {
+            map:SecondaryMap::new(),
+            clone_fn:None
         };
-        let clone_fn = move || {
-            let mut new = Self::new::<T>();
-            unsafe {
-                let org = ptr.as_ref().unwrap();
-                let new = new.get_mut::<T>();
-                *new = org.clone();
-            }
+        Self::from_erased(short_name::<T>(), Box::new(inner), false)
+    }
 
-            new
+    /// Like `new_runtime` but `clone` copies the components.
+    pub fn new_runtime_cloned<T:Component + Clone>() -> Self {
+        let inner = RuntimeStorage::<T> {
+            map:SecondaryMap::new(),
+            clone_fn:Some(clone_map::<T>)
         };
-        let default_fn = move |id| {
-            unsafe {
-                let storage = ptr.as_mut().unwrap();
-                if let Some(v) = storage.get_mut(id) {
-                    v.replace(T::default());
-                }
-            }
+        Self::from_erased(short_name::<T>(), Box::new(inner), false)
+    }
+
+    fn inner(&self) -> &dyn ErasedStorage {
+        if let Err(err) = self.inflate() {
+            panic!("failed to inflate {} storage: {}", self.name, err);
+        }
+        self.inner.get().expect("storage was inflated").as_ref()
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn ErasedStorage {
+        self.inner();
+        self.inner.get_mut().expect("storage was inflated").as_mut()
+    }
+
+    /// Keeps a compressed section as it is, it is inflated on the first access to the storage.
+    pub(crate) fn pack(&mut self, codec:Codec, bytes:Vec<u8>) {
+        self.ordered = false;
+        let empty = self.empty();
+        *self.packed.get_mut() = Some(Packed { codec, bytes, empty });
+    }
+
+    /// Whether the components were loaded, false while a section read by `Registry::deserialize_from`
+    /// is still compressed.
+    pub fn is_inflated(&self) -> bool {
+        self.inner.get().is_some()
+    }
+
+    /// Inflates a section left compressed by `Registry::deserialize_from`, accessing the storage does
+    /// the same but panics on a corrupt section. A corrupt section leaves the storage empty.
+    pub fn inflate(&self) -> io::Result<()> {
+        if self.is_inflated() {
+            return Ok(());
+        }
+        let Packed { codec, bytes, mut empty } = self.packed.borrow_mut().take().expect("storage is neither inflated nor packed");
+        let result = codec.decompress(bytes).and_then(|bytes| empty.deserialize(&mut bytes.as_slice()).map_err(bincode_error));
+        if result.is_err() {
+            empty.clear();
+        }
+        let _ = self.inner.set(empty);
+        result
+    }
+
+    /// Takes the components out as an empty storage, dropping a packed section without inflating it.
+    fn empty(&mut self) -> Box<dyn ErasedStorage> {
+        if let Some(packed) = self.packed.get_mut().take() {
+            return packed.empty;
+        }
+        let mut inner = self.inner.take().expect("storage is neither inflated nor packed");
+        inner.clear();
+        inner
+    }
+
+    /// Empty storage of the same type, for checking values without touching this one.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn scratch(&self) -> Storage {
+        let inner = match &*self.packed.borrow() {
+            Some(packed) => packed.empty.clone_empty(),
+            None => self.inner().clone_empty()
         };
-        let ptr = ptr as *mut ();
-        Self {
-            ptr,
-            drop_fn:Box::new(f),
-            serialize_fn:Box::new(serialize_fn),
-            deserialize_fn:Box::new(deserialize_fn),
-            remove_fn:Box::new(remove_fn),
-            clear_fn:Box::new(clear_fn),
-            clone_fn:Box::new(clone_fn),
-            default_fn:Box::new(default_fn)
-        }      
-    }
-    
-    pub unsafe fn get_mut<T>(&mut self) -> &mut SecondaryMap<EntityId, RefCell<T>> {
-        let ptr = self.ptr as *mut SecondaryMap<EntityId, RefCell<T>>;
-        unsafe {
-            return ptr.as_mut().unwrap();
+        Self::from_erased(self.name.clone(), inner, self.serialized)
+    }
+
+    /// Returns the components if the storage holds `T`.
+    pub fn get<T:'static>(&self) -> Option<&SecondaryMap<EntityId, RefCell<T>>> {
+        self.inner().map().downcast_ref()
+    }
+
+    /// Returns the components if the storage holds `T`.
+    pub fn get_mut<T:'static>(&mut self) -> Option<&mut SecondaryMap<EntityId, RefCell<T>>> {
+        self.ordered = false;
+        self.inner_mut().map_mut().downcast_mut()
+    }
+
+    pub(crate) fn typed<T:'static>(&self) -> &SecondaryMap<EntityId, RefCell<T>> {
+        match self.get() {
+            Some(map) => map,
+            None => panic!("{} storage does not hold {}", self.name, type_name::<T>())
         }
     }
 
-    pub unsafe fn get<T>(&self) -> &SecondaryMap<EntityId, RefCell<T>> {
-        let ptr = self.ptr as *const SecondaryMap<EntityId, RefCell<T>>;
-        unsafe {
-            return ptr.as_ref().unwrap();
+    pub(crate) fn typed_mut<T:'static>(&mut self) -> &mut SecondaryMap<EntityId, RefCell<T>> {
+        self.ordered = false;
+        if self.get::<T>().is_none() {
+            panic!("{} storage does not hold {}", self.name, type_name::<T>());
         }
-    }  
+        self.get_mut().unwrap()
+    }
 
     pub fn remove(&mut self, id:EntityId) {
-        self.remove_fn.as_mut()(id);
+        self.ordered = false;
+        self.inner_mut().remove(id);
+    }
+
+    /// Removes the component of `id` without knowing its type, for moving it to another storage.
+    pub fn take(&mut self, id:EntityId) -> Option<Box<dyn Any>> {
+        self.ordered = false;
+        self.inner_mut().take(id)
     }
 
-    pub unsafe fn deserialize(&mut self, bytes:&Vec<u8>) {
-        self.deserialize_fn.as_mut()(bytes);
+    /// Inserts a component returned by `take`, hands it back if the storage holds another type.
+    pub fn put(&mut self, id:EntityId, component:Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+        self.ordered = false;
+        self.inner_mut().put(id, component)
     }
 
-    pub unsafe fn serialize(&self, bytes:&mut Vec<u8>) {
-        self.serialize_fn.as_ref()(bytes);
+    /// Panics if `bytes` was not produced by `serialize` of a storage of the same type.
+    pub fn deserialize(&mut self, mut bytes:&[u8]) {
+        self.ordered = false;
+        self.inner = OnceCell::from(self.empty());
+        self.inner_mut().deserialize(&mut bytes).expect("failed to deserialize");
+    }
+
+    pub fn serialize(&self, bytes:&mut Vec<u8>) {
+        self.inner().serialize(bytes).expect("failed to serialize");
+    }
+
+    /// Number of bytes `serialize_to` writes.
+    pub fn serialized_size(&self) -> u64 {
+        self.inner().serialized_size()
+    }
+
+    pub fn serialize_to(&self, writer:&mut dyn Write) -> bincode::Result<()> {
+        self.inner().serialize(writer)
+    }
+
+    pub fn deserialize_from(&mut self, reader:&mut dyn Read) -> bincode::Result<()> {
+        self.ordered = false;
+        self.inner = OnceCell::from(self.empty());
+        self.inner_mut().deserialize(reader)
     }
 
     pub fn clear(&mut self) {
-        self.clear_fn.as_mut()();
+        self.ordered = false;
+        self.inner = OnceCell::from(self.empty());
     }
 
     pub fn default(&mut self, id:EntityId) {
-        self.default_fn.as_mut()(id);
-    } 
-}
+        self.ordered = false;
+        self.inner_mut().default(id);
+    }
+
+    pub fn insert_default(&mut self, id:EntityId) -> bool {
+        self.ordered = false;
+        self.inner_mut().insert_default(id)
+    }
 
-impl Drop for Storage {
-    fn drop(&mut self) {
-        self.drop_fn.as_mut()();
+    pub fn has(&self, id:EntityId) -> bool {
+        self.inner().has(id)
+    }
+
+    /// Whether `insert_default` can insert a value, false for runtime components.
+    pub fn has_default(&self) -> bool {
+        self.inner().has_default()
+    }
+
+    /// Whether both storages hold the same component type.
+    pub(crate) fn same_type(&self, other:&Storage) -> bool {
+        self.inner().map().type_id() == other.inner().map().type_id()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner().capacity()
+    }
+
+    /// Makes room for entities with an index below `capacity` without reallocating.
+    pub fn reserve(&mut self, capacity:usize) {
+        self.inner_mut().reserve(capacity);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.inner_mut().shrink_to_fit();
+    }
+
+    /// Bytes allocated by the storage itself, heap memory owned by the components is not included.
+    pub fn bytes(&self) -> usize {
+        (self.capacity() + 1) * self.inner().slot_size()
+    }
+
+    pub fn set_debug<T:Component + Debug>(&mut self) {
+        self.debug_fn = Some(|storage, id| {
+            let cell = storage.get::<T>()?.get(id)?;
+            match cell.try_borrow() {
+                Ok(component) => Some(format!("{:?}", component)),
+                Err(_) => Some("<borrowed>".to_string())
+            }
+        });
+    }
+
+    pub fn debug(&self, id:EntityId) -> Option<String> {
+        match self.debug_fn {
+            Some(debug_fn) => debug_fn(self, id),
+            None => self.reflect_get(id).map(|value| value.to_string())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether `debug` prints components with `Debug` instead of JSON.
+    pub fn has_debug(&self) -> bool {
+        self.debug_fn.is_some()
+    }
+
+    /// Whether the storage is sent by `ReplicationServer`.
+    pub fn is_replicated(&self) -> bool {
+        self.replicated
+    }
+
+    /// Whether the storage is saved by `Registry::serialize`, false for runtime components.
+    pub fn is_serialized(&self) -> bool {
+        self.serialized
+    }
+
+    /// Whether an index watches this storage, changes are then collected for the next refresh.
+    pub fn is_tracked(&self) -> bool {
+        self.tracked
+    }
+
+    /// Whether changes are collected for the next journal write.
+    pub fn is_journaled(&self) -> bool {
+        self.journaled
+    }
+
+    /// Whether the component of `id` changed since indexes were last refreshed.
+    pub fn is_touched(&self, id:EntityId) -> bool {
+        self.touched.borrow().contains_key(id)
+    }
+
+    /// Whether the component of `id` changed since the last journal write.
+    pub fn is_changed(&self, id:EntityId) -> bool {
+        self.changed.borrow().contains_key(id)
+    }
+
+    /// Number of times a component was skipped or not returned because it was already borrowed.
+    pub fn skipped(&self) -> usize {
+        self.skipped.get()
+    }
+
+    pub(crate) fn touch(&self, id:EntityId) {
+        if self.tracked {
+            self.touched.borrow_mut().insert(id, ());
+        }
+        self.record(id);
+    }
+
+    /// Marks the component of `id` for the next journal write, without touching indexes.
+    pub(crate) fn record(&self, id:EntityId) {
+        if self.journaled {
+            self.changed.borrow_mut().insert(id, ());
+        }
+    }
+
+    /// Entities in the order set by `Registry::sort`, `None` if components were added or removed since.
+    pub fn order(&self) -> Option<&[EntityId]> {
+        if self.ordered {
+            return Some(&self.order);
+        }
+        None
+    }
+
+    /// Takes the order for sorting it again, the flag tells whether it still matches the storage.
+    pub(crate) fn take_order(&mut self) -> (Vec<EntityId>, bool) {
+        let ordered = self.ordered;
+        self.ordered = false;
+        (take(&mut self.order), ordered)
+    }
+
+    pub(crate) fn set_order(&mut self, order:Vec<EntityId>) {
+        self.order = order;
+        self.ordered = true;
+    }
+
+    pub fn serialize_one(&self, id:EntityId) -> Option<Vec<u8>> {
+        self.inner().serialize_one(id)
+    }
+
+    /// Fails if `bytes` was not produced by `serialize_one` of a storage of the same type.
+    pub fn deserialize_one(&mut self, id:EntityId, bytes:&[u8]) -> bincode::Result<()> {
+        self.ordered = false;
+        self.inner_mut().deserialize_one(id, bytes)
+    }
+
+    pub fn reflect_get(&self, id:EntityId) -> Option<Value> {
+        self.inner().reflect_get(id)
+    }
+
+    pub fn reflect_set(&mut self, id:EntityId, value:Value) -> Result<(), ReflectError> {
+        self.ordered = false;
+        self.inner_mut().reflect_set(id, value)
+    }
+
+    pub fn reflect_default(&self) -> Value {
+        self.inner().reflect_default()
     }
 }
 
 impl Clone for Storage {
     fn clone(&self) -> Self {
-        self.clone_fn.as_ref()()
+        let mut clone = match &*self.packed.borrow() {
+            Some(packed) => {
+                let mut clone = Self::from_erased(self.name.clone(), packed.empty.clone_box(), self.serialized);
+                clone.pack(packed.codec, packed.bytes.clone());
+                clone
+            },
+            None => Self::from_erased(self.name.clone(), self.inner().clone_box(), self.serialized)
+        };
+        clone.replicated = self.replicated;
+        clone.debug_fn = self.debug_fn;
+        clone.tracked = self.tracked;
+        clone.order = self.order.clone();
+        clone.ordered = self.ordered;
+        clone
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Read, Write, ErrorKind};
+use uuid::Uuid;
+use crate::Codec;
+
+pub(crate) const MAGIC:&[u8; 4] = b"REGS";
+const VERSION:u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Section {
+    Entities,
+    Disabled,
+    Component,
+    Singleton,
+    Relation,
+    Journal,
+    Timers,
+    End
+}
+
+impl Section {
+    fn to_byte(self) -> u8 {
+        match self {
+            Section::Entities => 0,
+            Section::Disabled => 1,
+            Section::Component => 2,
+            Section::Singleton => 3,
+            Section::Relation => 4,
+            Section::Journal => 5,
+            Section::Timers => 6,
+            Section::End => 255
+        }
+    }
+
+    fn from_byte(byte:u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(Section::Entities),
+            1 => Ok(Section::Disabled),
+            2 => Ok(Section::Component),
+            3 => Ok(Section::Singleton),
+            4 => Ok(Section::Relation),
+            5 => Ok(Section::Journal),
+            6 => Ok(Section::Timers),
+            255 => Ok(Section::End),
+            _ => Err(invalid(format!("unknown section {}", byte)))
+        }
+    }
+}
+
+pub(crate) fn invalid<E:Into<Box<dyn std::error::Error + Send + Sync>>>(err:E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+#[allow(clippy::boxed_local)]
+pub(crate) fn bincode_error(err:bincode::Error) -> io::Error {
+    match *err {
+        bincode::ErrorKind::Io(err) => err,
+        err => invalid(err)
+    }
+}
+
+pub(crate) fn write_header(writer:&mut dyn Write, codec:Codec) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, codec.to_byte()])
+}
+
+/// Reads the stream header and returns the codec its sections were compressed with.
+pub(crate) fn read_header(reader:&mut dyn Read) -> io::Result<Codec> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a registry stream"));
+    }
+    match header[4] {
+        1 => Ok(Codec::None),
+        VERSION => {
+            let mut codec = [0; 1];
+            reader.read_exact(&mut codec)?;
+            Codec::from_byte(codec[0])
+        },
+        version => Err(invalid(format!("unsupported registry stream version {}", version)))
+    }
+}
+
+/// Writes the header of a section, `len` bytes of payload have to follow.
+pub(crate) fn write_section(writer:&mut dyn Write, section:Section, uuid:Uuid, len:u64) -> io::Result<()> {
+    writer.write_all(&[section.to_byte()])?;
+    writer.write_all(uuid.as_bytes())?;
+    writer.write_all(&len.to_le_bytes())
+}
+
+/// Writes a section whose payload is produced by `write`, `len` is the uncompressed payload size.
+/// Compressed sections are buffered so their compressed size can go in the header.
+pub(crate) fn write_compressed<F:FnOnce(&mut dyn Write) -> io::Result<()>>(writer:&mut dyn Write, codec:Codec, section:Section, uuid:Uuid, len:u64, write:F) -> io::Result<()> {
+    if codec == Codec::None {
+        write_section(writer, section, uuid, len)?;
+        return write(writer);
+    }
+    let mut bytes = Vec::with_capacity(len as usize);
+    write(&mut bytes)?;
+    let bytes = codec.compress(bytes);
+    write_section(writer, section, uuid, bytes.len() as u64)?;
+    writer.write_all(&bytes)
+}
+
+/// Hands the payload of a section to `read`, decompressing it first if needed.
+pub(crate) fn read_compressed<T, F:FnOnce(&mut dyn Read) -> io::Result<T>>(payload:&mut dyn Read, codec:Codec, read:F) -> io::Result<T> {
+    if codec == Codec::None {
+        return read(payload);
+    }
+    let mut bytes = Vec::new();
+    payload.read_to_end(&mut bytes)?;
+    read(&mut codec.decompress(bytes)?.as_slice())
+}
+
+pub(crate) fn read_section(reader:&mut dyn Read) -> io::Result<(Section, Uuid, u64)> {
+    let mut kind = [0; 1];
+    reader.read_exact(&mut kind)?;
+    let mut uuid = [0; 16];
+    reader.read_exact(&mut uuid)?;
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    Ok((Section::from_byte(kind[0])?, Uuid::from_bytes(uuid), u64::from_le_bytes(len)))
+}
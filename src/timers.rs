@@ -0,0 +1,252 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Display;
+use std::rc::Rc;
+use std::time::Duration;
+use fxhash::FxHashMap;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use slotmap::{new_key_type, SecondaryMap, SlotMap};
+use uuid::Uuid;
+use crate::Registry;
+
+new_key_type! {
+    /// Handle of a delayed command, see `Registry::cancel`.
+    pub struct TimerId;
+}
+
+/// When a delayed command runs, after a number of `Registry::execute` calls or once
+/// `Registry::advance` moved the clock far enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Delay {
+    Ticks(u64),
+    Duration(Duration)
+}
+
+impl From<u64> for Delay {
+    fn from(ticks:u64) -> Self {
+        Delay::Ticks(ticks)
+    }
+}
+
+impl From<Duration> for Delay {
+    fn from(duration:Duration) -> Self {
+        Delay::Duration(duration)
+    }
+}
+
+/// Command expressed as data, registered with `register_command`. Pending timed commands of
+/// this kind are saved by `serialize` and run after `deserialize`.
+pub trait TimedCommand : Serialize + DeserializeOwned + 'static {
+    fn type_id() -> Uuid;
+    fn run(self, registry:&mut Registry);
+}
+
+/// A timed command whose saved data could not be decoded, the command is dropped.
+#[derive(Debug)]
+pub struct CommandError {
+    pub command:Uuid,
+    pub error:bincode::Error
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to decode command {}: {}", self.command, self.error)
+    }
+}
+
+impl std::error::Error for CommandError {
+}
+
+type RunFn = fn(&mut Registry, &[u8]) -> bincode::Result<()>;
+
+fn run_command<C:TimedCommand>(registry:&mut Registry, bytes:&[u8]) -> bincode::Result<()> {
+    let command:C = bincode::deserialize(bytes)?;
+    command.run(registry);
+    Ok(())
+}
+
+#[derive(Clone)]
+pub(crate) enum Action {
+    Closure(Rc<dyn Fn(&mut Registry)>),
+    Data(Uuid, Vec<u8>)
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum Due {
+    Tick(u64),
+    Time(Duration)
+}
+
+#[derive(Clone)]
+struct Timer {
+    due:Due,
+    seq:u64,
+    action:Action
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializableTimers {
+    tick:u64,
+    time:Duration,
+    seq:u64,
+    keys:SlotMap<TimerId, ()>,
+    timers:Vec<(TimerId, Due, u64, Uuid, Vec<u8>)>
+}
+
+/// Pending delayed commands and the clock they are measured against. Commands are kept in
+/// `timers`, the heaps only order them and entries of cancelled timers are skipped when popped.
+#[derive(Default, Clone)]
+pub(crate) struct Timers {
+    tick:u64,
+    time:Duration,
+    seq:u64,
+    keys:SlotMap<TimerId, ()>,
+    timers:SecondaryMap<TimerId, Timer>,
+    ticks:BinaryHeap<Reverse<(u64, u64, TimerId)>>,
+    times:BinaryHeap<Reverse<(Duration, u64, TimerId)>>,
+    commands:FxHashMap<Uuid, RunFn>
+}
+
+impl Timers {
+    pub(crate) fn register<C:TimedCommand>(&mut self) {
+        if self.commands.insert(C::type_id(), run_command::<C>).is_some() {
+            panic!("{} command already registered!", std::any::type_name::<C>());
+        }
+    }
+
+    pub(crate) fn command(&self, id:Uuid) -> Option<RunFn> {
+        self.commands.get(&id).copied()
+    }
+
+    pub(crate) fn encode<C:TimedCommand>(&self, command:&C) -> Action {
+        if !self.commands.contains_key(&C::type_id()) {
+            panic!("{} command not registered!", std::any::type_name::<C>());
+        }
+        Action::Data(C::type_id(), bincode::serialize(command).expect("failed to serialize command"))
+    }
+
+    pub(crate) fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub(crate) fn time(&self) -> Duration {
+        self.time
+    }
+
+    pub(crate) fn advance(&mut self, elapsed:Duration) {
+        self.time += elapsed;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub(crate) fn contains(&self, id:TimerId) -> bool {
+        self.timers.contains_key(id)
+    }
+
+    /// Ticks run on a later `execute` even when zero, durations once the clock reaches them.
+    pub(crate) fn push(&mut self, delay:Delay, action:Action) -> TimerId {
+        let due = match delay {
+            Delay::Ticks(ticks) => Due::Tick(self.tick + ticks.max(1)),
+            Delay::Duration(duration) => Due::Time(self.time + duration)
+        };
+        let id = self.keys.insert(());
+        self.seq += 1;
+        self.insert(id, due, self.seq, action);
+        id
+    }
+
+    fn insert(&mut self, id:TimerId, due:Due, seq:u64, action:Action) {
+        match due {
+            Due::Tick(tick) => self.ticks.push(Reverse((tick, seq, id))),
+            Due::Time(time) => self.times.push(Reverse((time, seq, id)))
+        }
+        self.timers.insert(id, Timer { due, seq, action });
+    }
+
+    pub(crate) fn cancel(&mut self, id:TimerId) -> bool {
+        self.keys.remove(id);
+        self.timers.remove(id).is_some()
+    }
+
+    /// Advances the tick and removes the timers that are due, in the order they were pushed.
+    /// Commands of unregistered types stay pending until their type is registered.
+    pub(crate) fn due(&mut self) -> Vec<Action> {
+        self.tick += 1;
+        let mut due = Vec::new();
+        let mut unknown = Vec::new();
+        while let Some(Reverse((tick, _, id))) = self.ticks.peek().copied() {
+            if tick > self.tick {
+                break;
+            }
+            self.ticks.pop();
+            due.extend(self.take(id, &mut unknown));
+        }
+        while let Some(Reverse((time, _, id))) = self.times.peek().copied() {
+            if time > self.time {
+                break;
+            }
+            self.times.pop();
+            due.extend(self.take(id, &mut unknown));
+        }
+        for id in unknown {
+            let timer = &self.timers[id];
+            match timer.due {
+                Due::Tick(tick) => self.ticks.push(Reverse((tick, timer.seq, id))),
+                Due::Time(time) => self.times.push(Reverse((time, timer.seq, id)))
+            }
+        }
+        due.sort_by_key(|(seq, _)| *seq);
+        due.into_iter().map(|(_, action)| action).collect()
+    }
+
+    fn take(&mut self, id:TimerId, unknown:&mut Vec<TimerId>) -> Option<(u64, Action)> {
+        if let Action::Data(command, _) = &self.timers.get(id)?.action {
+            if !self.commands.contains_key(command) {
+                unknown.push(id);
+                return None;
+            }
+        }
+        self.keys.remove(id);
+        self.timers.remove(id).map(|timer| (timer.seq, timer.action))
+    }
+
+    /// Writes the clock and the timers holding command data, closures can not be saved and their
+    /// handles are invalid after loading.
+    pub(crate) fn serialize(&self) -> bincode::Result<Vec<u8>> {
+        let mut keys = self.keys.clone();
+        let mut timers = Vec::new();
+        for (id, timer) in self.timers.iter() {
+            match &timer.action {
+                Action::Data(command, bytes) => timers.push((id, timer.due, timer.seq, *command, bytes.clone())),
+                Action::Closure(_) => {
+                    keys.remove(id);
+                }
+            }
+        }
+        bincode::serialize(&SerializableTimers { tick:self.tick, time:self.time, seq:self.seq, keys, timers })
+    }
+
+    /// Replaces the timers with saved ones, keeping the registered command types.
+    pub(crate) fn deserialize(&mut self, bytes:&[u8]) -> bincode::Result<()> {
+        let saved:SerializableTimers = bincode::deserialize(bytes)?;
+        self.reset();
+        self.tick = saved.tick;
+        self.time = saved.time;
+        self.seq = saved.seq;
+        self.keys = saved.keys;
+        for (id, due, seq, command, bytes) in saved.timers {
+            self.insert(id, due, seq, Action::Data(command, bytes));
+        }
+        Ok(())
+    }
+
+    /// Drops all timers and restarts the clock.
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            commands:std::mem::take(&mut self.commands),
+            ..Default::default()
+        };
+    }
+}
@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::mem::take;
+use slotmap::{new_key_type, SlotMap};
+use crate::{AttachError, Component, EntityId, Registry, SerializableComponent};
+
+new_key_type! {
+    pub struct WorldId;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveError {
+    UnknownWorld(WorldId),
+    UnknownEntity(EntityId),
+    /// The target world has no storage of the same type for this component.
+    Unregistered(String),
+    /// A unique key or name of the entity is already held in the target world.
+    Conflict(AttachError)
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::UnknownWorld(world) => write!(f, "unknown world {:?}", world),
+            MoveError::UnknownEntity(id) => write!(f, "unknown entity {:?}", id),
+            MoveError::Unregistered(component) => write!(f, "{} is not registered in the target world", component),
+            MoveError::Conflict(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for MoveError {
+}
+
+type UniverseCommand = Box<dyn Fn(&mut Universe)>;
+
+/// Set of registries sharing the same registered components. Registration goes through the
+/// universe and is applied to every world, including worlds created later, registering on a
+/// single world panics.
+pub struct Universe {
+    template:Registry,
+    worlds:SlotMap<WorldId, Registry>,
+    commands:RefCell<Vec<UniverseCommand>>
+}
+
+impl Default for Universe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Universe {
+    pub fn new() -> Self {
+        Self {
+            template:Registry::new(),
+            worlds:SlotMap::with_key(),
+            commands:RefCell::new(Vec::new())
+        }
+    }
+
+    /// Runs `f` on every world and on the template new worlds are created from, use it for
+    /// registering components, relations, indexes and anything else all worlds should share.
+    pub fn register<F:Fn(&mut Registry)>(&mut self, f:F) {
+        f(&mut self.template);
+        for (_, world) in self.worlds.iter_mut() {
+            world.set_shared(false);
+            f(world);
+            world.set_shared(true);
+        }
+    }
+
+    pub fn register_component<T:SerializableComponent>(&mut self) {
+        self.register(|registry| registry.register_component::<T>());
+    }
+
+    pub fn register_runtime_component<T:Component>(&mut self) {
+        self.register(|registry| registry.register_runtime_component::<T>());
+    }
+
+    pub fn create_world(&mut self) -> WorldId {
+        let mut world = self.template.clone();
+        world.set_shared(true);
+        self.worlds.insert(world)
+    }
+
+    /// Returns the world as a standalone registry that accepts registrations again.
+    pub fn remove_world(&mut self, world:WorldId) -> Option<Registry> {
+        let mut world = self.worlds.remove(world)?;
+        world.set_shared(false);
+        Some(world)
+    }
+
+    pub fn world(&self, world:WorldId) -> Option<&Registry> {
+        self.worlds.get(world)
+    }
+
+    pub fn world_mut(&mut self, world:WorldId) -> Option<&mut Registry> {
+        self.worlds.get_mut(world)
+    }
+
+    pub fn worlds(&self) -> impl Iterator<Item = (WorldId, &Registry)> {
+        self.worlds.iter()
+    }
+
+    pub fn worlds_mut(&mut self) -> impl Iterator<Item = (WorldId, &mut Registry)> {
+        self.worlds.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.worlds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.worlds.is_empty()
+    }
+
+    /// Moves `id` with all its components to `to` and returns its id there. Relations of the entity are
+    /// dropped and ids stored inside components, like `Parent`, are not remapped. Nothing is moved
+    /// if any of the components can not be stored in `to`.
+    pub fn move_entity(&mut self, from:WorldId, id:EntityId, to:WorldId) -> Result<EntityId, MoveError> {
+        let source = self.worlds.get(from).ok_or(MoveError::UnknownWorld(from))?;
+        let target = self.worlds.get(to).ok_or(MoveError::UnknownWorld(to))?;
+        if !source.contains(id) {
+            return Err(MoveError::UnknownEntity(id));
+        }
+        if from == to {
+            return Ok(id);
+        }
+        if let Some(component) = source.unmovable(id, target) {
+            let name = source.component_name(component).map(str::to_string).unwrap_or_else(|| component.to_string());
+            return Err(MoveError::Unregistered(name));
+        }
+        if let Some(err) = target.unique_conflicts(source, id) {
+            return Err(MoveError::Conflict(err));
+        }
+        let (components, enabled) = self.worlds[from].take_entity(id).ok_or(MoveError::UnknownEntity(id))?;
+        Ok(self.worlds[to].put_entity(components, enabled).expect("conflicts were checked before taking the entity"))
+    }
+
+    /// Queues `f` to run on `world` on the next `execute`, skipped if the world was removed by then.
+    pub fn push<F:Fn(&mut Registry) + 'static>(&self, world:WorldId, f:F) {
+        self.push_universe(move |universe| {
+            if let Some(world) = universe.world_mut(world) {
+                f(world);
+            }
+        });
+    }
+
+    /// Queues `f` to run on the whole universe, for commands that create worlds or move entities.
+    pub fn push_universe<F:Fn(&mut Universe) + 'static>(&self, f:F) {
+        self.commands.borrow_mut().push(Box::new(f));
+    }
+
+    /// Runs the queued universe commands in order, then the commands queued on each world.
+    pub fn execute(&mut self) {
+        let commands = take(self.commands.get_mut());
+        for command in commands {
+            command(self);
+        }
+        for (_, world) in self.worlds.iter_mut() {
+            world.execute();
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Components that are already borrowed, reported as errors, skipped or panicking in strict mode.
+
+use registry::{BorrowError, Component, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Mana(i32);
+
+impl Component for Mana {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.register_component::<Mana>();
+    registry
+}
+
+#[test]
+fn try_component() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(1)).id();
+    assert_eq!(registry.try_component::<Mana>(a).err(), Some(BorrowError::Missing));
+    assert_eq!(registry.try_component_mut::<Mana>(a).err(), Some(BorrowError::Missing));
+
+    let held = registry.component_mut::<Health>(a).unwrap();
+    assert_eq!(registry.try_component::<Health>(a).err(), Some(BorrowError::Borrowed));
+    assert_eq!(registry.try_component_mut::<Health>(a).err(), Some(BorrowError::Borrowed));
+    assert!(registry.component::<Health>(a).is_none());
+    assert_eq!(registry.skipped::<Health>(), 3);
+    drop(held);
+
+    let shared = registry.component::<Health>(a).unwrap();
+    assert_eq!(registry.try_component::<Health>(a).map(|health| health.0), Ok(1));
+    assert_eq!(registry.try_component_mut::<Health>(a).err(), Some(BorrowError::Borrowed));
+    drop(shared);
+    registry.try_component_mut::<Health>(a).unwrap().0 = 2;
+    assert_eq!(registry.component::<Health>(a).map(|health| health.0), Some(2));
+    assert_eq!(registry.skipped::<Health>(), 4);
+}
+
+#[test]
+fn try_iter() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(1)).id();
+    let b = registry.spawn().attach(Health(2)).id();
+    let healths = registry.components::<Health>();
+    let held = healths.get_mut(a).unwrap();
+
+    let values:Vec<_> = healths.try_iter().map(|(id, health)| (id, health.map(|health| health.0))).collect();
+    assert_eq!(values, [(a, Err(BorrowError::Borrowed)), (b, Ok(2))]);
+    for (id, health) in healths.try_iter_mut() {
+        match health {
+            Ok(mut health) => health.0 += 10,
+            Err(err) => assert_eq!((id, err), (a, BorrowError::Borrowed))
+        }
+    }
+    assert_eq!(registry.skipped::<Health>(), 0);
+
+    let skipped:Vec<_> = healths.iter().map(|(id, _)| id).collect();
+    assert_eq!(skipped, [b]);
+    assert_eq!(healths.iter_mut().count(), 1);
+    assert_eq!(registry.skipped::<Health>(), 2);
+    drop(held);
+    let values:Vec<_> = healths.iter().map(|(_, health)| health.0).collect();
+    assert_eq!(values, [1, 12]);
+}
+
+#[test]
+fn lenient_by_default() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(1)).id();
+    let _held = registry.component_mut::<Health>(a).unwrap();
+    assert!(registry.component::<Health>(a).is_none());
+    assert_eq!(registry.components::<Health>().iter().count(), 0);
+}
+
+#[test]
+fn strict_mode_keeps_try_variants() {
+    let mut registry = registry();
+    registry.set_strict_borrows(true);
+    let a = registry.spawn().attach(Health(1)).id();
+    let _held = registry.component_mut::<Health>(a).unwrap();
+    let healths = registry.components::<Health>();
+    assert!(healths.try_iter().all(|(_, health)| health.err() == Some(BorrowError::Borrowed)));
+    assert_eq!(registry.try_component::<Mana>(a).err(), Some(BorrowError::Missing));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "is already borrowed")]
+fn strict_mode_panics() {
+    let mut registry = registry();
+    registry.set_strict_borrows(true);
+    let a = registry.spawn().attach(Health(1)).id();
+    let _held = registry.component_mut::<Health>(a).unwrap();
+    let _ = registry.component::<Health>(a);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "is already borrowed")]
+fn strict_mode_panics_while_iterating() {
+    let mut registry = registry();
+    registry.set_strict_borrows(true);
+    let a = registry.spawn().attach(Health(1)).id();
+    let healths = registry.components::<Health>();
+    let _held = healths.get(a).unwrap();
+    healths.iter_mut().for_each(drop);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn strict_mode_can_be_turned_off() {
+    let mut registry = registry();
+    registry.set_strict_borrows(true);
+    registry.set_strict_borrows(false);
+    let a = registry.spawn().attach(Health(1)).id();
+    let _held = registry.component_mut::<Health>(a).unwrap();
+    assert!(registry.component::<Health>(a).is_none());
+}
@@ -0,0 +1,107 @@
+//! Registry streams written with each codec.
+
+use registry::{Codec, Component, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Label(String);
+
+impl Component for Label {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.register_component::<Label>();
+    registry
+}
+
+/// Checks that sections stay compressed until used and that a corrupt one fails on `inflate`.
+#[cfg(any(feature = "lz4", feature = "deflate"))]
+fn lazy(codec:Codec) {
+    let mut registry = registry();
+    registry.set_codec(codec);
+    let id = registry.spawn().attach(Health(7)).attach(Label("seven".to_string())).id();
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    assert!(!other.is_inflated::<Health>());
+    assert!(!other.is_inflated::<Label>());
+    let clone = other.clone();
+    assert!(!clone.is_inflated::<Health>());
+    assert_eq!(other.component::<Health>(id).map(|health| health.0), Some(7));
+    assert!(other.is_inflated::<Health>());
+    assert!(!other.is_inflated::<Label>());
+    assert!(other.inflate().is_ok());
+    assert_eq!(other.component::<Label>(id).map(|label| label.0.clone()), Some("seven".to_string()));
+    assert_eq!(clone.component::<Label>(id).map(|label| label.0.clone()), Some("seven".to_string()));
+
+    // a component section starts with its kind, the uuid and the payload length
+    let mut header = vec![2];
+    header.extend_from_slice(Health::type_id().as_bytes());
+    let start = bytes.windows(17).position(|window| window == header).unwrap() + 17 + 8;
+    bytes[start..start + 4].copy_from_slice(&[1, 0, 0, 0]);
+    let mut corrupt = self::registry();
+    corrupt.deserialize(&bytes);
+    assert!(corrupt.inflate().is_err());
+    assert!(corrupt.is_inflated::<Health>());
+    assert!(!corrupt.component_has::<Health>(id));
+    assert!(corrupt.component_has::<Label>(id));
+}
+
+fn round_trip(codec:Codec) {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.set_codec(codec);
+    let ids:Vec<_> = (0..100).map(|i| registry.spawn().attach(Health(i % 3)).id()).collect();
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+
+    let mut other = Registry::new();
+    other.register_component::<Health>();
+    other.deserialize(&bytes);
+    for (i, id) in ids.into_iter().enumerate() {
+        assert_eq!(other.component::<Health>(id).map(|health| health.0), Some(i as i32 % 3));
+    }
+}
+
+#[test]
+fn uncompressed() {
+    round_trip(Codec::None);
+    let mut registry = registry();
+    let id = registry.spawn().attach(Health(7)).id();
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    assert!(other.is_inflated::<Health>());
+    assert_eq!(other.component::<Health>(id).map(|health| health.0), Some(7));
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn lz4() {
+    round_trip(Codec::Lz4);
+    lazy(Codec::Lz4);
+}
+
+#[cfg(feature = "deflate")]
+#[test]
+fn deflate() {
+    round_trip(Codec::Deflate);
+    lazy(Codec::Deflate);
+}
@@ -0,0 +1,123 @@
+//! Disabled entities are skipped by iteration but kept by `iter_all`, clones and saves.
+
+use std::cell::RefMut;
+use registry::{Component, Components, EntityFacade, EntityId, Facade, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+struct HealthFacade<'a> {
+    registry:&'a Registry,
+    healths:Components<'a, Health>
+}
+
+impl<'a> Facade<'a> for HealthFacade<'a> {
+    fn new(registry:&'a Registry) -> Self {
+        Self {
+            registry,
+            healths:registry.components::<Health>()
+        }
+    }
+
+    fn registry(&self) -> &'a Registry {
+        self.registry
+    }
+}
+
+struct Living<'a> {
+    id:EntityId,
+    health:RefMut<'a, Health>
+}
+
+impl<'a> EntityFacade<'a> for Living<'a> {
+    type Facade = HealthFacade<'a>;
+    fn query(facade:&'a Self::Facade, id:EntityId) -> Option<Self> {
+        Some(Self {
+            id,
+            health:facade.healths.get_mut(id)?
+        })
+    }
+}
+
+fn registry() -> (Registry, EntityId, EntityId) {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    let a = registry.spawn().attach(Health(1)).id();
+    let b = registry.spawn().attach(Health(2)).id();
+    registry.disable(b);
+    (registry, a, b)
+}
+
+#[test]
+fn iteration_skips_disabled() {
+    let (registry, a, b) = registry();
+    assert!(registry.is_enabled(a));
+    assert!(!registry.is_enabled(b));
+    assert!(registry.contains(b));
+    assert_eq!(registry.len(), 2);
+    assert_eq!(registry.iter().collect::<Vec<_>>(), [a]);
+    assert_eq!(registry.iter_all().collect::<Vec<_>>(), [a, b]);
+
+    let healths = registry.components::<Health>();
+    assert_eq!(healths.iter().map(|(id, _)| id).collect::<Vec<_>>(), [a]);
+    for (_, mut health) in healths.iter_mut() {
+        health.0 += 10;
+    }
+    assert_eq!(healths.iter_all().map(|(_, health)| health.0).collect::<Vec<_>>(), [11, 2]);
+    assert_eq!(healths.iter_mut_all().count(), 2);
+    assert_eq!(healths.get(b).map(|health| health.0), Some(2));
+}
+
+#[test]
+fn query_skips_disabled() {
+    let (registry, a, b) = registry();
+    let facade = registry.facade::<HealthFacade>();
+    let queried:Vec<_> = facade.query::<Living>().map(|living| living.id).collect();
+    assert_eq!(queried, [a]);
+    for mut living in facade.query_all::<Living>() {
+        living.health.0 = 0;
+    }
+    assert_eq!(registry.component::<Health>(b).map(|health| health.0), Some(0));
+}
+
+#[test]
+fn enable() {
+    let (mut registry, a, b) = registry();
+    registry.enable(b);
+    assert!(registry.is_enabled(b));
+    assert_eq!(registry.iter().collect::<Vec<_>>(), [a, b]);
+    registry.despawn(b);
+    let c = registry.spawn().id();
+    assert!(registry.is_enabled(c));
+}
+
+#[test]
+fn clone_and_serialize_keep_disabled() {
+    let (mut registry, a, b) = registry();
+    let clone = registry.clone();
+    assert!(clone.is_enabled(a));
+    assert!(!clone.is_enabled(b));
+
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    let (mut other, ..) = self::registry();
+    other.deserialize(&bytes);
+    assert!(other.is_enabled(a));
+    assert!(!other.is_enabled(b));
+
+    let mut bytes = Vec::new();
+    registry.enable(b);
+    registry.disable(a);
+    registry.serialize_to(&mut bytes).unwrap();
+    other.deserialize_from(bytes.as_slice()).unwrap();
+    assert!(!other.is_enabled(a));
+    assert!(other.is_enabled(b));
+    assert_eq!(other.iter().collect::<Vec<_>>(), [b]);
+}
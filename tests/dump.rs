@@ -0,0 +1,103 @@
+//! Text dumps of a registry.
+
+use registry::{Component, Name, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Label {
+    text:String
+}
+
+impl Component for Label {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Score(u32);
+
+impl Component for Score {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_debug_component::<Health>();
+    registry.register_component::<Label>();
+    registry.register_singleton::<Score>();
+    let a = registry.spawn().attach(Health(5)).attach(Name::new("a")).id();
+    registry.spawn().attach(Label { text:"b".to_string() });
+    registry.disable(a);
+    registry
+}
+
+#[test]
+fn dump() {
+    let registry = registry();
+    let expected = "\
+Registry { entities: 2, components: 4, singletons: 1 }
+Storages:
+  Health: 1
+  Label: 1
+  registry::Name: 1
+  registry::Parent: 0
+Singletons:
+  Score: 0
+Entities:
+  EntityId(1v1) \"a\" (disabled)
+    Health: Health(5)
+    registry::Name: Name(\"a\")
+  EntityId(2v1)
+    Label: {\"text\":\"b\"}
+";
+    assert_eq!(registry.dump().to_string(), expected);
+    assert_eq!(format!("{:?}", registry), expected);
+}
+
+#[test]
+fn filters() {
+    let mut registry = registry();
+    registry.register_debug::<Label>();
+    registry.register_debug::<Score>();
+    let b = registry.iter().next().unwrap();
+    let expected = "\
+Registry { entities: 2, components: 1, singletons: 0 }
+Storages:
+  Label: 1
+Singletons:
+Entities:
+  EntityId(2v1)
+    Label: Label { text: \"b\" }
+";
+    assert_eq!(registry.dump().component::<Label>().to_string(), expected);
+
+    let dump = registry.dump().entity(b).to_string();
+    assert!(dump.contains("Score: Score(0)"));
+    assert!(dump.contains("  EntityId(2v1)\n    Label: Label { text: \"b\" }\n"));
+    assert!(!dump.contains("EntityId(1v1)"));
+
+    let dump = registry.dump().component::<Label>().entity(registry.find_by_name("a").unwrap()).to_string();
+    assert!(dump.ends_with("Entities:\n"));
+    let dump = registry.dump().component_id(Health::type_id()).component::<Score>().to_string();
+    assert!(dump.contains("components: 1, singletons: 1"));
+    assert!(dump.contains("    Health: Health(5)\n"));
+}
+
+#[test]
+#[should_panic(expected = "type not registered")]
+fn debug_of_unregistered() {
+    let mut registry = Registry::new();
+    registry.register_debug::<Health>();
+}
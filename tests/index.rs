@@ -0,0 +1,193 @@
+//! Index lookups, including several indexes borrowed at the same time.
+
+use registry::{AttachError, Component, Name, ReflectError, Registry, uuid::Uuid, serde_json::json};
+use serde::{Serialize, Deserialize};
+
+struct Pid(u32);
+
+impl Component for Pid {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+struct Position([f32; 2]);
+
+impl Component for Position {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct Code {
+    id:u32
+}
+
+impl Component for Code {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_runtime_component::<Pid>();
+    registry.register_runtime_component::<Position>();
+    registry.register_component::<Code>();
+    registry
+}
+
+#[test]
+fn holds_two_indexes_at_once() {
+    let mut registry = registry();
+    let pids = registry.create_index::<Pid, u32>(|pid| pid.0);
+    let parity = registry.create_index::<Pid, bool>(|pid| pid.0 % 2 == 0);
+    let positions = registry.create_spatial_index::<Position>(1.0, |position| position.0);
+    let a = registry.spawn().attach(Pid(1)).attach(Position([0.0, 0.0])).id();
+    let b = registry.spawn().attach(Pid(2)).attach(Position([5.0, 5.0])).id();
+
+    let by_pid = registry.index(&pids);
+    let by_parity = registry.index(&parity);
+    let spatial = registry.spatial_index(&positions);
+    assert_eq!(by_pid.first(&1), Some(a));
+    assert_eq!(by_parity.get(&true), &[b]);
+    assert_eq!(spatial.nearest_k([4.0, 4.0], 1), vec![b]);
+}
+
+#[test]
+fn changes_while_borrowed_apply_on_next_lookup() {
+    let mut registry = registry();
+    let pids = registry.create_index::<Pid, u32>(|pid| pid.0);
+    let a = registry.spawn().attach(Pid(1)).id();
+    {
+        let held = registry.index(&pids);
+        registry.component_mut::<Pid>(a).unwrap().0 = 7;
+        assert_eq!(registry.index(&pids).first(&1), Some(a));
+        assert_eq!(held.first(&7), None);
+    }
+    assert_eq!(registry.index(&pids).first(&7), Some(a));
+    assert_eq!(registry.index(&pids).first(&1), None);
+}
+
+#[test]
+fn unique_index_rejects_duplicates() {
+    let mut registry = registry();
+    registry.create_unique_index::<Pid, u32>(|pid| pid.0);
+    let a = registry.spawn().attach(Pid(1)).id();
+    let b = registry.spawn().id();
+    assert!(registry.component_try_attach(b, Pid(1)).is_err());
+    assert!(registry.component_try_attach(b, Pid(2)).is_ok());
+    assert!(registry.component_has::<Pid>(a));
+}
+
+#[test]
+fn spatial_queries_far_apart() {
+    let mut registry = registry();
+    let positions = registry.create_spatial_index::<Position>(1.0, |position| position.0);
+    let a = registry.spawn().attach(Position([0.0, 0.0])).id();
+    let b = registry.spawn().attach(Position([1000.0, 1000.0])).id();
+    let c = registry.spawn().attach(Position([1e12, -1e12])).id();
+    let d = registry.spawn().attach(Position([-1e12, 1e12])).id();
+
+    let spatial = registry.spatial_index(&positions);
+    assert_eq!(spatial.nearest_k([0.0, 0.0], 2), vec![a, b]);
+    assert_eq!(spatial.nearest_k([1e12, -1e12], 1), vec![c]);
+    assert_eq!(spatial.nearest_k([-1e12, 1e12], 4)[0], d);
+    let mut found = spatial.within_aabb([-1e13, -1e13], [1e13, 1e13]);
+    found.sort();
+    let mut all = vec![a, b, c, d];
+    all.sort();
+    assert_eq!(found, all);
+    assert_eq!(spatial.within_aabb([-1.0, -1.0], [1.0, 1.0]), vec![a]);
+    assert_eq!(spatial.within_radius([999.0, 999.0], 2.0), vec![b]);
+}
+
+#[test]
+fn name_lookups_follow_changes() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Name::new("a")).id();
+    registry.component_mut::<Name>(a).unwrap().0 = "b".to_string();
+    assert_eq!(registry.find_by_name("a"), None);
+    assert_eq!(registry.find_by_name("b"), Some(a));
+
+    let mut name = registry.component_mut::<Name>(a).unwrap();
+    name.0 = "c".to_string();
+    assert_eq!(registry.find_by_name("c"), None);
+    drop(name);
+    assert_eq!(registry.find_by_name("c"), Some(a));
+    for (_, mut name) in registry.components::<Name>().iter_mut() {
+        name.0 = "d".to_string();
+    }
+    assert_eq!(registry.find_by_name("c"), None);
+    assert_eq!(registry.find_by_name("d"), Some(a));
+    registry.component_detach::<Name>(a);
+    assert_eq!(registry.find_by_name("d"), None);
+}
+
+#[test]
+fn reflected_writes_keep_keys_unique() {
+    let mut registry = registry();
+    let codes = registry.create_unique_index::<Code, u32>(|code| code.id);
+    let a = registry.spawn().attach(Code { id:7 }).id();
+    let b = registry.spawn().attach(Code { id:1 }).id();
+    let c = registry.spawn().id();
+    let err = registry.set_component_dyn(b, Code::type_id(), json!({ "id": 7 })).unwrap_err();
+    assert!(matches!(err, ReflectError::Conflict(AttachError::UniqueViolation { .. })));
+    assert_eq!(registry.component::<Code>(b).map(|code| code.id), Some(1));
+    assert!(registry.set_component_dyn(c, Code::type_id(), json!({ "id": 7 })).is_err());
+    assert!(!registry.component_has::<Code>(c));
+    assert_eq!(registry.index(&codes).get(&7), [a]);
+    assert!(registry.set_component_dyn(c, Code::type_id(), json!({ "id": 8 })).is_ok());
+    assert_eq!(registry.index(&codes).first(&8), Some(c));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "takes the unique key")]
+fn mutation_into_a_taken_key_panics_in_debug() {
+    let mut registry = registry();
+    let codes = registry.create_unique_index::<Code, u32>(|code| code.id);
+    registry.spawn().attach(Code { id:7 });
+    let b = registry.spawn().attach(Code { id:1 }).id();
+    registry.component_mut::<Code>(b).unwrap().id = 7;
+    registry.index(&codes);
+}
+
+#[test]
+fn swapping_keys_through_mutation_is_allowed() {
+    let mut registry = registry();
+    let codes = registry.create_unique_index::<Code, u32>(|code| code.id);
+    let a = registry.spawn().attach(Code { id:1 }).id();
+    let b = registry.spawn().attach(Code { id:2 }).id();
+    registry.component_mut::<Code>(a).unwrap().id = 2;
+    registry.component_mut::<Code>(b).unwrap().id = 1;
+    assert_eq!(registry.index(&codes).first(&2), Some(a));
+    assert_eq!(registry.index(&codes).first(&1), Some(b));
+}
+
+#[test]
+fn unique_names() {
+    let mut registry = registry();
+    registry.set_unique_names(true);
+    let a = registry.spawn().attach(Name::new("a")).id();
+    let b = registry.spawn().id();
+    assert!(matches!(registry.component_try_attach(b, Name::new("a")), Err(AttachError::DuplicateName { .. })));
+    let err = registry.set_component_dyn(b, Name::type_id(), json!("a")).unwrap_err();
+    assert!(matches!(err, ReflectError::Conflict(AttachError::DuplicateName { .. })));
+    assert_eq!(registry.find_all_by_name("a"), [a]);
+    assert!(registry.set_component_dyn(b, Name::type_id(), json!("b")).is_ok());
+    assert_eq!(registry.find_by_name("b"), Some(b));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "is not unique")]
+fn renaming_into_a_taken_name_panics_in_debug() {
+    let mut registry = registry();
+    registry.set_unique_names(true);
+    registry.spawn().attach(Name::new("a"));
+    let b = registry.spawn().attach(Name::new("b")).id();
+    registry.component_mut::<Name>(b).unwrap().0 = "a".to_string();
+    registry.find_by_name("a");
+}
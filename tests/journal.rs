@@ -0,0 +1,204 @@
+//! Journal replay, including journals that were cut off by a crash in the middle of a record.
+
+use registry::{Component, EntityId, Name, Registry, Relation, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Position(i32, i32);
+
+impl Component for Position {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Score(u32);
+
+impl Component for Score {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+struct Follows;
+
+impl Relation for Follows {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x4)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Position>();
+    registry.register_component::<Health>();
+    registry.register_singleton::<Score>();
+    registry.register_relation::<Follows>();
+    registry
+}
+
+fn state(registry:&Registry) -> Vec<(EntityId, Option<Position>, Option<Health>, bool)> {
+    let mut state:Vec<_> = registry.iter_all().map(|id| {
+        let position = registry.component::<Position>(id).map(|position| position.clone());
+        let health = registry.component::<Health>(id).map(|health| health.clone());
+        (id, position, health, registry.is_enabled(id))
+    }).collect();
+    state.sort_by_key(|(id, ..)| *id);
+    state
+}
+
+fn recover(snapshot:&[u8], journal:&[u8]) -> (Registry, usize) {
+    let mut registry = registry();
+    let replayed = registry.recover(snapshot, journal).unwrap();
+    (registry, replayed)
+}
+
+#[test]
+fn replays_changes() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Position(1, 1)).attach(Health(10)).id();
+    let b = registry.spawn().attach(Health(20)).id();
+    let mut snapshot = Vec::new();
+    registry.compact(&mut snapshot).unwrap();
+    let mut journal = registry.start_journal(Vec::new()).unwrap();
+
+    registry.component_mut::<Position>(a).unwrap().0 = 5;
+    for (_, mut health) in registry.components::<Health>().iter_mut() {
+        health.0 -= 1;
+    }
+    registry.component_detach::<Health>(a);
+    registry.despawn(b);
+    let c = registry.spawn().attach(Position(3, 3)).attach(Name::new("c")).id();
+    let d = registry.reserve_entity();
+    registry.execute();
+    registry.component_attach(d, Health(1));
+    registry.disable(c);
+    assert!(registry.write_journal(&mut journal).unwrap() > 0);
+    assert_eq!(registry.write_journal(&mut journal).unwrap(), 0);
+
+    let (recovered, replayed) = recover(&snapshot, journal.get_ref());
+    assert_eq!(replayed, journal.records());
+    assert_eq!(state(&recovered), state(&registry));
+    assert_eq!(recovered.find_by_name("c"), Some(c));
+    assert!(!recovered.contains(b));
+}
+
+#[test]
+fn truncated_mid_record() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(10)).id();
+    let mut snapshot = Vec::new();
+    registry.compact(&mut snapshot).unwrap();
+    let mut journal = registry.start_journal(Vec::new()).unwrap();
+    registry.component_mut::<Health>(a).unwrap().0 = 9;
+    registry.write_journal(&mut journal).unwrap();
+    let committed = state(&registry);
+    let len = journal.get_ref().len();
+
+    registry.component_mut::<Health>(a).unwrap().0 = 8;
+    registry.spawn().attach(Position(2, 2));
+    registry.write_journal(&mut journal).unwrap();
+    let bytes = journal.into_inner();
+
+    let mut last = 0;
+    for cut in len..bytes.len() {
+        let (recovered, replayed) = recover(&snapshot, &bytes[..cut]);
+        if cut == len {
+            assert_eq!(state(&recovered), committed);
+        }
+        assert!(replayed >= last);
+        last = replayed;
+        assert!(matches!(recovered.component::<Health>(a).map(|health| health.0), Some(9) | Some(8)));
+    }
+    assert!(last < 4);
+    let (recovered, _) = recover(&snapshot, &bytes);
+    assert_eq!(state(&recovered), state(&registry));
+}
+
+#[test]
+fn corrupt_record_stops_replay() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(10)).id();
+    let mut snapshot = Vec::new();
+    registry.compact(&mut snapshot).unwrap();
+    let mut journal = registry.start_journal(Vec::new()).unwrap();
+    registry.component_mut::<Health>(a).unwrap().0 = 9;
+    registry.write_journal(&mut journal).unwrap();
+    let mut bytes = journal.into_inner();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+
+    let (recovered, replayed) = recover(&snapshot, &bytes);
+    assert_eq!(replayed, 0);
+    assert_eq!(recovered.component::<Health>(a).map(|health| health.0), Some(10));
+}
+
+#[test]
+fn compaction_supersedes_journal() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(10)).id();
+    let mut old_snapshot = Vec::new();
+    registry.compact(&mut old_snapshot).unwrap();
+    let mut old_journal = registry.start_journal(Vec::new()).unwrap();
+    registry.component_mut::<Health>(a).unwrap().0 = 1;
+    registry.write_journal(&mut old_journal).unwrap();
+
+    registry.component_mut::<Health>(a).unwrap().0 = 2;
+    let mut snapshot = Vec::new();
+    registry.compact(&mut snapshot).unwrap();
+
+    let (recovered, replayed) = recover(&snapshot, old_journal.get_ref());
+    assert_eq!(replayed, 0);
+    assert_eq!(recovered.component::<Health>(a).map(|health| health.0), Some(2));
+
+    let (recovered, _) = recover(&old_snapshot, old_journal.get_ref());
+    assert_eq!(recovered.component::<Health>(a).map(|health| health.0), Some(1));
+
+    let (recovered, replayed) = recover(&snapshot, &[]);
+    assert_eq!(replayed, 0);
+    assert_eq!(state(&recovered), state(&registry));
+}
+
+#[test]
+fn replays_relations_and_singletons() {
+    let mut registry = registry();
+    let a = registry.spawn().id();
+    let b = registry.spawn().id();
+    let c = registry.spawn().id();
+    registry.relate::<Follows>(a, b);
+    let mut snapshot = Vec::new();
+    registry.compact(&mut snapshot).unwrap();
+    let mut journal = registry.start_journal(Vec::new()).unwrap();
+
+    registry.relate::<Follows>(a, c);
+    registry.relate::<Follows>(c, b);
+    registry.unrelate::<Follows>(a, b);
+    registry.singleton_mut::<Score>().unwrap().0 = 42;
+    registry.write_journal(&mut journal).unwrap();
+
+    let (recovered, _) = recover(&snapshot, journal.get_ref());
+    assert!(!recovered.is_related::<Follows>(a, b));
+    assert!(recovered.is_related::<Follows>(a, c));
+    assert!(recovered.is_related::<Follows>(c, b));
+    assert_eq!(recovered.singleton::<Score>().map(|score| score.0), Some(42));
+
+    registry.insert_singleton(Score(7));
+    registry.write_journal(&mut journal).unwrap();
+    let (recovered, _) = recover(&snapshot, journal.get_ref());
+    assert_eq!(recovered.singleton::<Score>().map(|score| score.0), Some(7));
+
+    registry.remove_singleton::<Score>();
+    registry.write_journal(&mut journal).unwrap();
+    let (recovered, _) = recover(&snapshot, journal.get_ref());
+    assert!(!recovered.has_singleton::<Score>());
+}
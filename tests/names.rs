@@ -0,0 +1,87 @@
+//! Entity names, paths through `Parent` links and unique names.
+
+use registry::{AttachError, EntityId, Name, Parent, Registry};
+
+/// `root/arm/hand` and `other/arm`, with a second unparented `arm`.
+fn tree(registry:&mut Registry) -> [EntityId; 5] {
+    let root = registry.spawn().attach(Name::new("root")).id();
+    let arm = registry.spawn().attach(Name::new("arm")).attach(Parent(root)).id();
+    let hand = registry.spawn().attach(Name::new("hand")).attach(Parent(arm)).id();
+    let other = registry.spawn().attach(Name::new("other")).id();
+    let other_arm = registry.spawn().attach(Name::new("arm")).attach(Parent(other)).id();
+    [root, arm, hand, other, other_arm]
+}
+
+#[test]
+fn find_by_path() {
+    let mut registry = Registry::new();
+    let [root, arm, hand, other, other_arm] = tree(&mut registry);
+    assert_eq!(registry.find_by_path("root"), Some(root));
+    assert_eq!(registry.find_by_path("root/arm"), Some(arm));
+    assert_eq!(registry.find_by_path("/root/arm/hand"), Some(hand));
+    assert_eq!(registry.find_by_path("root//arm/hand/"), Some(hand));
+    assert_eq!(registry.find_by_path("other/arm"), Some(other_arm));
+    assert_eq!(registry.find_by_path("other"), Some(other));
+    assert_eq!(registry.find_all_by_name("arm").len(), 2);
+
+    // paths start at a root, partial paths and wrong parents do not match
+    assert_eq!(registry.find_by_path("arm"), None);
+    assert_eq!(registry.find_by_path("arm/hand"), None);
+    assert_eq!(registry.find_by_path("other/arm/hand"), None);
+    assert_eq!(registry.find_by_path("root/hand"), None);
+    assert_eq!(registry.find_by_path(""), None);
+    assert_eq!(registry.find_by_path("/"), None);
+
+    let lone = registry.spawn().attach(Name::new("arm")).id();
+    assert_eq!(registry.find_by_path("arm"), Some(lone));
+}
+
+#[test]
+fn find_by_path_follows_changes() {
+    let mut registry = Registry::new();
+    let [root, arm, hand, other, _] = tree(&mut registry);
+    registry.component_attach(arm, Parent(other));
+    assert_eq!(registry.find_by_path("root/arm/hand"), None);
+    assert_eq!(registry.find_by_path("other/arm/hand"), Some(hand));
+
+    registry.component_mut::<Name>(root).unwrap().0 = "base".to_string();
+    assert_eq!(registry.find_by_path("base"), Some(root));
+    assert_eq!(registry.find_by_path("root"), None);
+
+    registry.despawn(other);
+    assert_eq!(registry.find_by_path("other/arm"), None);
+    assert_eq!(registry.find_by_path("other/arm/hand"), None);
+}
+
+#[test]
+fn unique_names() {
+    let mut registry = Registry::new();
+    registry.set_unique_names(true);
+    let a = registry.spawn().attach(Name::new("a")).id();
+    let b = registry.spawn().id();
+    let err = registry.component_try_attach(b, Name::new("a")).unwrap_err();
+    assert_eq!(err.to_string(), format!("name 'a' already used by {:?} \"a\"", a));
+    assert!(!registry.component_has::<Name>(b));
+
+    // replacing the name of the holder with the same name is fine
+    assert!(registry.component_try_attach(a, Name::new("a")).is_ok());
+    registry.despawn(a);
+    assert!(registry.component_try_attach(b, Name::new("a")).is_ok());
+    assert_eq!(registry.find_by_name("a"), Some(b));
+
+    registry.set_unique_names(false);
+    let c = registry.spawn().attach(Name::new("a")).id();
+    assert_eq!(registry.find_all_by_name("a"), [b, c]);
+}
+
+#[test]
+fn unique_names_in_paths() {
+    let mut registry = Registry::new();
+    registry.set_unique_names(true);
+    let root = registry.spawn().attach(Name::new("root")).id();
+    let child = registry.spawn().attach(Name::new("child")).attach(Parent(root)).id();
+    let other = registry.spawn().id();
+    registry.component_attach(other, Parent(root));
+    assert!(matches!(registry.component_try_attach(other, Name::new("child")), Err(AttachError::DuplicateName { .. })));
+    assert_eq!(registry.find_by_path("root/child"), Some(child));
+}
@@ -0,0 +1,113 @@
+//! The text query language and component lookup by name.
+
+use registry::{Component, QueryError, Registry, uuid::Uuid, serde_json::json};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Position {
+    x:i32,
+    y:i32
+}
+
+impl Component for Position {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health {
+    amount:i32
+}
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+mod other {
+    use super::*;
+
+    #[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Health;
+
+    impl Component for Health {
+        fn type_id() -> Uuid {
+            Uuid::from_u128(0x3)
+        }
+    }
+}
+
+mod user {
+    use super::*;
+
+    #[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Name(pub String);
+
+    impl Component for Name {
+        fn type_id() -> Uuid {
+            Uuid::from_u128(0x4)
+        }
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Position>();
+    registry.register_component::<Health>();
+    for (x, amount) in [(3, 5), (1, 20), (2, 8)] {
+        registry.spawn().attach(Position { x, y:0 }).attach(Health { amount });
+    }
+    registry.spawn().attach(Position { x:4, y:0 });
+    registry
+}
+
+#[test]
+fn filters_and_orders() {
+    let registry = registry();
+    let result = registry.query("Position.x, Health.amount where Health.amount < 10 order by Position.x desc").unwrap();
+    assert_eq!(result.columns, ["Position.x", "Health.amount"]);
+    let values:Vec<_> = result.rows.iter().map(|row| row.values.clone()).collect();
+    assert_eq!(values, [vec![json!(3), json!(5)], vec![json!(2), json!(8)]]);
+
+    let result = registry.query("Position.x where not Health order by Position.x").unwrap();
+    assert_eq!(result.rows.iter().map(|row| row.values[0].clone()).collect::<Vec<_>>(), [json!(4)]);
+    let result = registry.query("Position.x order by Position.x limit 2 offset 1").unwrap();
+    let values:Vec<_> = result.rows.iter().map(|row| row.values[0].clone()).collect();
+    assert_eq!(values, [json!(2), json!(3)]);
+}
+
+#[test]
+fn addresses_components_by_uuid() {
+    let registry = registry();
+    let query = format!("{}.amount order by {}.amount", Health::type_id(), Health::type_id());
+    let result = registry.query(&query).unwrap();
+    assert_eq!(result.rows.first().map(|row| row.values[0].clone()), Some(json!(5)));
+}
+
+#[test]
+fn reports_errors() {
+    let registry = registry();
+    assert_eq!(registry.query("Velocity").unwrap_err(), QueryError::UnknownComponent("Velocity".to_string()));
+    assert!(matches!(registry.query("Position where"), Err(QueryError::Syntax { .. })));
+    assert!(matches!(registry.query("Position limit x"), Err(QueryError::Syntax { .. })));
+}
+
+#[test]
+#[should_panic(expected = "a component named Health is already registered")]
+fn rejects_ambiguous_names() {
+    let mut registry = registry();
+    registry.register_component::<other::Health>();
+}
+
+#[test]
+fn built_in_components_are_namespaced() {
+    let mut registry = registry();
+    registry.register_component::<user::Name>();
+    let id = registry.spawn().attach(Position { x:9, y:0 }).attach(registry::Name::new("x")).attach(user::Name("y".to_string())).id();
+    let result = registry.query("Position.x, Name where registry::Name == 'x'").unwrap();
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0].id, id);
+    assert_eq!(result.rows[0].values, [json!(9), json!("y")]);
+}
@@ -0,0 +1,149 @@
+//! Relations between entities and how they are saved.
+
+use registry::{Component, EntityId, Registry, Relation, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+struct Follows;
+
+impl Relation for Follows {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+struct Owns;
+
+impl Relation for Owns {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.register_relation::<Follows>();
+    registry.register_relation::<Owns>();
+    registry
+}
+
+fn related(registry:&mut Registry) -> [EntityId; 3] {
+    let a = registry.spawn().attach(Health(1)).id();
+    let b = registry.spawn().attach(Health(2)).id();
+    let c = registry.spawn().id();
+    registry.relate::<Follows>(a, b);
+    registry.relate::<Follows>(a, c);
+    registry.relate::<Follows>(c, b);
+    registry.relate::<Owns>(b, a);
+    [a, b, c]
+}
+
+fn check(registry:&Registry, [a, b, c]:[EntityId; 3]) {
+    assert_eq!(registry.targets::<Follows>(a), [b, c]);
+    assert_eq!(registry.sources::<Follows>(b), [a, c]);
+    assert_eq!(registry.targets::<Owns>(b), [a]);
+    assert!(registry.is_related::<Follows>(c, b));
+    assert!(!registry.is_related::<Follows>(b, a));
+}
+
+#[test]
+fn relate_and_unrelate() {
+    let mut registry = registry();
+    let [a, b, c] = related(&mut registry);
+    check(&registry, [a, b, c]);
+    assert!(registry.targets::<Owns>(a).is_empty());
+    assert!(registry.sources::<Follows>(a).is_empty());
+
+    registry.relate::<Follows>(a, b);
+    assert_eq!(registry.targets::<Follows>(a), [b, c]);
+
+    assert!(registry.unrelate::<Follows>(a, b));
+    assert!(!registry.unrelate::<Follows>(a, b));
+    assert_eq!(registry.targets::<Follows>(a), [c]);
+    assert_eq!(registry.sources::<Follows>(b), [c]);
+    assert!(registry.is_related::<Owns>(b, a));
+}
+
+#[test]
+fn ignores_dead_entities() {
+    let mut registry = registry();
+    let a = registry.spawn().id();
+    let b = registry.spawn().id();
+    registry.despawn(b);
+    registry.relate::<Follows>(a, b);
+    registry.relate::<Follows>(b, a);
+    assert!(registry.targets::<Follows>(a).is_empty());
+    assert!(registry.sources::<Follows>(a).is_empty());
+}
+
+#[test]
+fn despawn_removes_edges() {
+    let mut registry = registry();
+    let [a, b, c] = related(&mut registry);
+    registry.despawn(b);
+    assert_eq!(registry.targets::<Follows>(a), [c]);
+    assert!(registry.targets::<Follows>(c).is_empty());
+    assert!(registry.sources::<Follows>(b).is_empty());
+    assert!(registry.sources::<Owns>(a).is_empty());
+
+    let d = registry.spawn().id();
+    assert!(registry.sources::<Follows>(d).is_empty());
+    registry.clear();
+    assert!(registry.targets::<Follows>(a).is_empty());
+}
+
+#[test]
+fn serialize() {
+    let mut registry = registry();
+    let ids = related(&mut registry);
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    check(&other, ids);
+    check(&registry.clone(), ids);
+}
+
+#[test]
+fn serialize_to() {
+    let mut registry = registry();
+    let ids = related(&mut registry);
+    let mut bytes = Vec::new();
+    registry.serialize_to(&mut bytes).unwrap();
+    let mut other = self::registry();
+    let stale = other.spawn().id();
+    other.relate::<Follows>(stale, stale);
+    other.deserialize_from(bytes.as_slice()).unwrap();
+    check(&other, ids);
+    assert!(!other.is_related::<Follows>(stale, stale));
+}
+
+#[test]
+fn unregistered_relations_are_skipped() {
+    let mut registry = registry();
+    let [a, b, _] = related(&mut registry);
+    let mut bytes = Vec::new();
+    registry.serialize_to(&mut bytes).unwrap();
+    let mut other = Registry::new();
+    other.register_component::<Health>();
+    other.register_relation::<Owns>();
+    other.deserialize_from(bytes.as_slice()).unwrap();
+    assert_eq!(other.targets::<Owns>(b), [a]);
+    assert_eq!(other.component::<Health>(a).map(|health| health.0), Some(1));
+}
+
+#[test]
+#[should_panic(expected = "relation already registered")]
+fn register_twice() {
+    let mut registry = registry();
+    registry.register_relation::<Follows>();
+}
@@ -0,0 +1,128 @@
+//! Server to client replication over an in-process transport.
+
+use registry::{ChannelTransport, Component, EntityId, Registry, Replicate, ReplicationClient, ReplicationPacket, ReplicationServer, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Position(i32, i32);
+
+impl Component for Position {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+impl Replicate for Position {
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Secret(i32);
+
+impl Component for Secret {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_replicated::<Position>();
+    registry.register_component::<Secret>();
+    registry
+}
+
+struct Session {
+    server:ReplicationServer,
+    client:ReplicationClient,
+    server_transport:ChannelTransport,
+    client_transport:ChannelTransport,
+    remote:Registry
+}
+
+impl Session {
+    fn new() -> Self {
+        let (server_transport, client_transport) = ChannelTransport::pair();
+        Self {
+            server:ReplicationServer::new(),
+            client:ReplicationClient::new(),
+            server_transport,
+            client_transport,
+            remote:registry()
+        }
+    }
+
+    fn sync(&mut self, registry:&Registry) -> usize {
+        self.server.send(registry, &mut self.server_transport);
+        self.client.receive(&mut self.remote, &mut self.client_transport)
+    }
+
+    fn position(&self, id:EntityId) -> Option<Position> {
+        self.remote.component::<Position>(self.client.local(id)?).map(|position| position.clone())
+    }
+}
+
+#[test]
+fn replicates_changes() {
+    let mut registry = registry();
+    let mut session = Session::new();
+    let a = registry.spawn().attach(Position(1, 2)).attach(Secret(9)).id();
+    let b = registry.spawn().attach(Position(3, 4)).id();
+    assert_eq!(session.sync(&registry), 1);
+    assert_eq!(session.position(a), Some(Position(1, 2)));
+    assert_eq!(session.position(b), Some(Position(3, 4)));
+    assert!(session.remote.component::<Secret>(session.client.local(a).unwrap()).is_none());
+
+    assert_eq!(session.sync(&registry), 0);
+
+    registry.component_mut::<Position>(a).unwrap().0 = 5;
+    registry.despawn(b);
+    assert_eq!(session.sync(&registry), 1);
+    assert_eq!(session.position(a), Some(Position(5, 2)));
+    assert_eq!(session.client.local(b), None);
+    assert_eq!(session.remote.len(), 1);
+
+    registry.component_detach::<Position>(a);
+    session.sync(&registry);
+    assert_eq!(session.client.local(a), None);
+    assert!(session.remote.is_empty());
+}
+
+#[test]
+fn replicates_disabled_entities() {
+    let mut registry = registry();
+    let mut session = Session::new();
+    let a = registry.spawn().attach(Position(1, 1)).id();
+    registry.disable(a);
+    session.sync(&registry);
+    assert_eq!(session.position(a), Some(Position(1, 1)));
+    let local = session.client.local(a).unwrap();
+    assert!(!session.remote.is_enabled(local));
+
+    registry.enable(a);
+    assert_eq!(session.sync(&registry), 1);
+    assert!(session.remote.is_enabled(local));
+
+    registry.disable(a);
+    assert_eq!(session.sync(&registry), 1);
+    assert!(!session.remote.is_enabled(local));
+}
+
+#[test]
+fn skips_malformed_components() {
+    let mut registry = registry();
+    let mut server = ReplicationServer::new();
+    let mut client = ReplicationClient::new();
+    let mut remote = self::registry();
+    let a = registry.spawn().attach(Position(1, 1)).id();
+    let b = registry.spawn().attach(Position(2, 2)).id();
+    let mut packet = server.update(&registry);
+    let corrupt = packet.updated.iter_mut().find(|(id, ..)| *id == a).unwrap();
+    corrupt.2.truncate(1);
+
+    let decoded = ReplicationPacket::decode(&packet.encode()).unwrap();
+    assert!(client.apply(&mut remote, &decoded).is_err());
+    let local = client.local(b).unwrap();
+    assert_eq!(remote.component::<Position>(local).map(|position| position.clone()), Some(Position(2, 2)));
+    assert!(remote.component::<Position>(client.local(a).unwrap()).is_none());
+    assert!(ReplicationPacket::decode(&[1, 2, 3]).is_none());
+}
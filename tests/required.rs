@@ -0,0 +1,113 @@
+//! Required components, including attaches that fail halfway.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use registry::{AttachError, Component, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Body(i32);
+
+impl Component for Body {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Slot(u32);
+
+impl Component for Slot {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Mass(f32);
+
+impl Component for Mass {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+struct Handle;
+
+impl Component for Handle {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x4)
+    }
+}
+
+struct Grip;
+
+impl Component for Grip {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x5)
+    }
+
+    fn required() -> Vec<Uuid> {
+        vec![Handle::type_id()]
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Body>();
+    registry.register_component::<Slot>();
+    registry.register_component::<Mass>();
+    registry.require::<Body, Mass, _>(|| Mass(1.0));
+    registry.require::<Body, Slot, _>(|| Slot(1));
+    registry.create_unique_index::<Slot, u32>(|slot| slot.0);
+    registry
+}
+
+#[test]
+fn failed_attach_is_rolled_back() {
+    let mut registry = registry();
+    registry.spawn().attach(Slot(1));
+    let id = registry.spawn().id();
+    let err = registry.component_try_attach(id, Body(5)).unwrap_err();
+    assert!(matches!(err, AttachError::UniqueViolation { .. }));
+    assert!(!registry.component_has::<Body>(id));
+    assert!(!registry.component_has::<Mass>(id));
+    assert!(!registry.component_has::<Slot>(id));
+}
+
+#[test]
+fn failed_replace_restores_previous() {
+    let mut registry = registry();
+    let id = registry.spawn().attach(Body(5)).id();
+    registry.component_detach::<Slot>(id);
+    registry.spawn().attach(Slot(1));
+    assert!(registry.component_try_attach(id, Body(6)).is_err());
+    assert_eq!(registry.component::<Body>(id).map(|body| body.0), Some(5));
+    assert!(registry.component_has::<Mass>(id));
+}
+
+#[test]
+fn cycle_is_rejected_before_recording() {
+    let mut registry = registry();
+    let result = catch_unwind(AssertUnwindSafe(|| registry.require::<Slot, Body, _>(Body::default)));
+    assert!(result.is_err());
+    let id = registry.spawn().id();
+    registry.component_attach(id, Slot(2));
+    assert!(!registry.component_has::<Body>(id));
+}
+
+#[test]
+#[should_panic(expected = "has no default value")]
+fn required_runtime_component_is_rejected_at_registration() {
+    let mut registry = Registry::new();
+    registry.register_runtime_component::<Grip>();
+    registry.register_runtime_component::<Handle>();
+}
+
+#[test]
+#[should_panic(expected = "component type not registered")]
+fn unregistered_attach_panics_before_required_check() {
+    let mut registry = registry();
+    registry.set_strict_required(true);
+    let id = registry.spawn().id();
+    let _ = registry.component_try_attach(id, Grip);
+}
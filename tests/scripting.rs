@@ -0,0 +1,71 @@
+//! The Rhai script API.
+#![cfg(feature = "scripting")]
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use registry::{Component, Registry, Scripting, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health {
+    amount:i64
+}
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry
+}
+
+#[test]
+fn scripts_edit_components() {
+    let mut registry = registry();
+    let scripting = Scripting::new();
+    let id = registry.spawn().attach(Health { amount:3 }).id();
+    let result = scripting.run(&mut registry, r#"
+        let id = spawn_entity();
+        attach(id, "Health", #{ amount: 7 });
+        for other in query(["Health"]) {
+            set(other, "Health", "amount", get(other, "Health", "amount") + 1);
+        }
+        get(id, "Health", "amount")
+    "#).unwrap();
+    assert_eq!(result.as_int(), Ok(8));
+    assert_eq!(registry.len(), 2);
+    assert_eq!(registry.component::<Health>(id).map(|health| health.amount), Some(4));
+    assert!(scripting.run(&mut registry, r#"attach(spawn_entity(), "Velocity")"#).is_err());
+}
+
+#[test]
+fn reports_failed_deferred_attach() {
+    let mut registry = registry();
+    let scripting = Scripting::new();
+    let deferred = scripting.run(&mut registry, r#"
+        let id = spawn_entity();
+        defer_attach(id, "Health", #{ amount: 1 });
+        defer_despawn_entity(id);
+        defer_attach(id, "Health", #{ amount: 2 });
+    "#);
+    assert!(deferred.is_ok());
+    registry.execute();
+    assert!(registry.is_empty());
+    assert_eq!(scripting.run(&mut registry, "1").unwrap().as_int(), Ok(1));
+    assert!(!scripting.take_errors().is_empty());
+    assert!(scripting.take_errors().is_empty());
+}
+
+#[test]
+fn restores_registry_after_panic() {
+    let mut registry = registry();
+    let mut scripting = Scripting::new();
+    scripting.engine_mut().register_fn("fail", || -> i64 { panic!("script failed") });
+    let id = registry.spawn().attach(Health { amount:1 }).id();
+    let result = catch_unwind(AssertUnwindSafe(|| scripting.run(&mut registry, "fail()")));
+    assert!(result.is_err());
+    assert_eq!(registry.component::<Health>(id).map(|health| health.amount), Some(1));
+}
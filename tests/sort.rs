@@ -0,0 +1,161 @@
+//! Orders kept by `Registry::sort` and the iterators following them.
+
+use std::cell::Ref;
+use registry::{Component, Components, EntityFacade, EntityId, Facade, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Rank(i32);
+
+impl Component for Rank {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Tag;
+
+impl Component for Tag {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+struct RankFacade<'a> {
+    registry:&'a Registry,
+    ranks:Components<'a, Rank>
+}
+
+impl<'a> Facade<'a> for RankFacade<'a> {
+    fn new(registry:&'a Registry) -> Self {
+        Self {
+            registry,
+            ranks:registry.components::<Rank>()
+        }
+    }
+
+    fn registry(&self) -> &'a Registry {
+        self.registry
+    }
+}
+
+struct Ranked<'a> {
+    rank:Ref<'a, Rank>
+}
+
+impl<'a> EntityFacade<'a> for Ranked<'a> {
+    type Facade = RankFacade<'a>;
+    fn query(facade:&'a Self::Facade, id:EntityId) -> Option<Self> {
+        Some(Self {
+            rank:facade.ranks.get(id)?
+        })
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Rank>();
+    registry.register_component::<Tag>();
+    registry
+}
+
+fn spawn(registry:&mut Registry, ranks:&[i32]) -> Vec<EntityId> {
+    ranks.iter().map(|rank| registry.spawn().attach(Rank(*rank)).id()).collect()
+}
+
+fn ranks(registry:&Registry) -> Vec<i32> {
+    registry.components::<Rank>().iter().map(|(_, rank)| rank.0).collect()
+}
+
+#[test]
+fn sort() {
+    let mut registry = registry();
+    let ids = spawn(&mut registry, &[3, 1, 2]);
+    assert_eq!(ranks(&registry), [3, 1, 2]);
+    registry.sort::<Rank, _>(|a, b| a.0.cmp(&b.0));
+    assert_eq!(ranks(&registry), [1, 2, 3]);
+    let sorted:Vec<_> = registry.iter_sorted::<Rank>().collect();
+    assert_eq!(sorted, [ids[1], ids[2], ids[0]]);
+
+    registry.sort_by_key::<Rank, _, _>(|rank| -rank.0);
+    assert_eq!(ranks(&registry), [3, 2, 1]);
+    let mutated:Vec<_> = registry.components::<Rank>().iter_mut().map(|(id, _)| id).collect();
+    assert_eq!(mutated, [ids[0], ids[2], ids[1]]);
+
+    // changing values keeps the order until the next sort
+    registry.component_mut::<Rank>(ids[1]).unwrap().0 = 10;
+    assert_eq!(ranks(&registry), [3, 2, 10]);
+    registry.sort_by_key::<Rank, _, _>(|rank| -rank.0);
+    assert_eq!(ranks(&registry), [10, 3, 2]);
+}
+
+#[test]
+fn attach_and_detach_invalidate() {
+    let mut registry = registry();
+    let ids = spawn(&mut registry, &[3, 1, 2]);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    registry.component_attach(ids[0], Tag);
+    assert_eq!(ranks(&registry), [1, 2, 3]);
+
+    let id = registry.spawn().attach(Rank(0)).id();
+    assert_eq!(ranks(&registry), [3, 1, 2, 0]);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    assert_eq!(ranks(&registry), [0, 1, 2, 3]);
+
+    registry.component_detach::<Rank>(id);
+    assert_eq!(ranks(&registry), [3, 1, 2]);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    registry.despawn(ids[1]);
+    assert_eq!(ranks(&registry), [3, 2]);
+    let sorted:Vec<_> = registry.iter_sorted::<Rank>().collect();
+    assert_eq!(sorted, [ids[0], ids[2]]);
+}
+
+#[test]
+fn skips_disabled() {
+    let mut registry = registry();
+    let ids = spawn(&mut registry, &[3, 1, 2]);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    registry.disable(ids[2]);
+    assert_eq!(ranks(&registry), [1, 3]);
+    let sorted:Vec<_> = registry.iter_sorted::<Rank>().collect();
+    assert_eq!(sorted, [ids[1], ids[0]]);
+    let all:Vec<_> = registry.components::<Rank>().iter_all().map(|(_, rank)| rank.0).collect();
+    assert_eq!(all, [1, 2, 3]);
+    let keyed:Vec<_> = registry.components::<Rank>().iter_sorted_by_key(|rank| -rank.0).map(|(_, rank)| rank.0).collect();
+    assert_eq!(keyed, [3, 1]);
+}
+
+#[test]
+fn iter_sorted_by_key() {
+    let mut registry = registry();
+    spawn(&mut registry, &[2, 5, 1, 4]);
+    let components = registry.components::<Rank>();
+    let keyed:Vec<_> = components.iter_sorted_by_key(|rank| rank.0).map(|(_, rank)| rank.0).collect();
+    assert_eq!(keyed, [1, 2, 4, 5]);
+    let keyed:Vec<_> = components.iter_sorted_by_key(|rank| rank.0 % 2).map(|(_, rank)| rank.0).collect();
+    assert_eq!(keyed, [2, 4, 5, 1]);
+    assert_eq!(ranks(&registry), [2, 5, 1, 4]);
+}
+
+#[test]
+fn query_sorted() {
+    let mut registry = registry();
+    let ids = spawn(&mut registry, &[3, 1, 2]);
+    registry.spawn().attach(Tag);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    registry.disable(ids[0]);
+    let facade = registry.facade::<RankFacade>();
+    let queried:Vec<_> = facade.query_sorted::<Ranked, Rank>().map(|ranked| ranked.rank.0).collect();
+    assert_eq!(queried, [1, 2]);
+}
+
+#[test]
+fn clone_keeps_order() {
+    let mut registry = registry();
+    spawn(&mut registry, &[3, 1, 2]);
+    registry.sort_by_key::<Rank, _, _>(|rank| rank.0);
+    let clone = registry.clone();
+    assert_eq!(ranks(&clone), [1, 2, 3]);
+}
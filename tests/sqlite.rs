@@ -0,0 +1,156 @@
+//! Export to and import from SQLite, run with `cargo test --features sqlite`.
+#![cfg(feature = "sqlite")]
+
+use std::collections::BTreeMap;
+use registry::{Component, Name, Registry, rusqlite::Connection, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Position {
+    x:i32,
+    y:f32,
+    label:String
+}
+
+impl Component for Position {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Inv(BTreeMap<String, u32>);
+
+impl Component for Inv {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum State {
+    #[default]
+    Idle,
+    Moving { speed:u32 }
+}
+
+impl Component for State {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Agent {
+    state:State,
+    tags:Vec<String>
+}
+
+impl Component for Agent {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x4)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Position>();
+    registry.register_component::<Inv>();
+    registry.register_component::<State>();
+    registry.register_component::<Agent>();
+    registry
+}
+
+fn populated() -> Registry {
+    let mut registry = registry();
+    registry.spawn()
+        .attach(Position { x:1, y:2.5, label:"a".to_string() })
+        .attach(Inv(BTreeMap::from([("gold".to_string(), 5)])))
+        .attach(State::Moving { speed:3 });
+    let b = registry.spawn()
+        .attach(State::Idle)
+        .attach(Agent { state:State::Moving { speed:1 }, tags:vec!["x".to_string()] })
+        .id();
+    registry.disable(b);
+    registry
+}
+
+fn contents(registry:&Registry) -> Vec<String> {
+    let mut ids:Vec<_> = registry.iter_all().collect();
+    ids.sort();
+    ids.into_iter().map(|id| format!("{:?} {:?} {:?} {:?} {:?} {}", id,
+        registry.component::<Position>(id).map(|c| c.clone()),
+        registry.component::<Inv>(id).map(|c| c.clone()),
+        registry.component::<State>(id).map(|c| c.clone()),
+        registry.component::<Agent>(id).map(|c| c.clone()),
+        registry.is_enabled(id))).collect()
+}
+
+#[test]
+fn round_trip_maps_and_enums() {
+    let registry = populated();
+    let connection = Connection::open_in_memory().unwrap();
+    registry.export_sqlite(&connection).unwrap();
+
+    let mut imported = self::registry();
+    imported.import_sqlite(&connection).unwrap();
+    assert_eq!(contents(&imported), contents(&registry));
+}
+
+#[test]
+fn failed_import_keeps_registry() {
+    let registry = populated();
+    let connection = Connection::open_in_memory().unwrap();
+    registry.export_sqlite(&connection).unwrap();
+    connection.execute("UPDATE State SET value = '\"Flying\"'", []).unwrap();
+
+    let mut imported = populated();
+    let before = contents(&imported);
+    assert!(imported.import_sqlite(&connection).is_err());
+    assert_eq!(contents(&imported), before);
+
+    connection.execute("DROP TABLE components", []).unwrap();
+    assert!(imported.import_sqlite(&connection).is_err());
+    assert_eq!(contents(&imported), before);
+}
+
+#[test]
+fn import_checks_unique_keys_and_entities() {
+    let mut registry = populated();
+    registry.spawn().attach(Position { x:1, y:0.0, label:"b".to_string() });
+    let connection = Connection::open_in_memory().unwrap();
+    registry.export_sqlite(&connection).unwrap();
+
+    let mut imported = self::registry();
+    imported.create_unique_index::<Position, i32>(|position| position.x);
+    let id = imported.spawn().attach(Position::default()).id();
+    assert!(imported.import_sqlite(&connection).is_err());
+    assert_eq!(imported.iter_all().collect::<Vec<_>>(), [id]);
+
+    connection.execute("UPDATE Position SET x = 7 WHERE label = 'b'", []).unwrap();
+    imported.import_sqlite(&connection).unwrap();
+    assert_eq!(contents(&imported).len(), 3);
+
+    connection.execute_batch("PRAGMA foreign_keys = OFF; DELETE FROM entities WHERE enabled = 0;").unwrap();
+    let before = contents(&imported);
+    assert!(imported.import_sqlite(&connection).is_err());
+    assert_eq!(contents(&imported), before);
+}
+
+#[test]
+fn import_checks_unique_names() {
+    let mut registry = populated();
+    let ids:Vec<_> = registry.iter_all().collect();
+    registry.component_attach(ids[0], Name::new("same"));
+    registry.component_attach(ids[1], Name::new("same"));
+    let connection = Connection::open_in_memory().unwrap();
+    registry.export_sqlite(&connection).unwrap();
+
+    let mut imported = self::registry();
+    imported.set_unique_names(true);
+    assert!(imported.import_sqlite(&connection).is_err());
+    assert!(imported.is_empty());
+    imported.set_unique_names(false);
+    imported.import_sqlite(&connection).unwrap();
+    assert_eq!(imported.find_all_by_name("same").len(), 2);
+}
@@ -0,0 +1,111 @@
+//! Memory reports and the capacity management they describe.
+
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::num::NonZeroU32;
+use registry::{Component, Registry, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Score(u64);
+
+impl Component for Score {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+/// Bytes of one slot of a storage of `T`.
+fn slot<T>() -> usize {
+    size_of::<Option<(NonZeroU32, RefCell<T>)>>()
+}
+
+/// Bytes of one entity slot, a version and the alive flag.
+const META:usize = 8;
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.register_singleton::<Score>();
+    registry
+}
+
+#[test]
+fn counts() {
+    let mut registry = registry();
+    let ids:Vec<_> = (0..3).map(|i| registry.spawn().attach(Health(i)).id()).collect();
+    registry.despawn(ids[1]);
+    registry.reserve_entities(2);
+    registry.push(|_| ());
+    registry.push(|_| ());
+
+    let stats = registry.stats();
+    assert_eq!(stats.entities, 2);
+    assert_eq!(stats.free_entities, 1);
+    assert_eq!(stats.reserved_entities, 2);
+    assert_eq!(stats.pending_commands, 2);
+    let health = stats.component(Health::type_id()).unwrap();
+    assert_eq!(health.name, "Health");
+    assert_eq!(health.len, 2);
+    assert_eq!(health.bytes, (health.capacity + 1) * slot::<Health>());
+    assert_eq!(stats.singletons.len(), 1);
+    assert_eq!(stats.singletons[0].len, 1);
+    let components:usize = stats.components.iter().map(|component| component.bytes).sum();
+    assert_eq!(stats.bytes(), stats.entity_bytes + components + stats.singletons[0].bytes);
+    let report = stats.to_string();
+    assert!(report.contains("pending commands: 2"));
+    assert!(report.contains("Health: 2"));
+
+    registry.execute();
+    let stats = registry.stats();
+    assert_eq!(stats.entities, 4);
+    assert_eq!(stats.free_entities, 0);
+    assert_eq!(stats.reserved_entities, 0);
+    assert_eq!(stats.pending_commands, 0);
+}
+
+#[test]
+fn reserve_and_shrink() {
+    let mut registry = registry();
+    let ids:Vec<_> = (0..3).map(|i| registry.spawn().attach(Health(i)).id()).collect();
+    registry.despawn(ids[1]);
+    registry.reserve_entities(2);
+
+    // flushes the two reserved ids into slots 2 and 4
+    registry.shrink_to_fit();
+    let stats = registry.stats();
+    assert_eq!(stats.entities, 4);
+    assert_eq!(stats.entity_capacity, 4);
+    assert_eq!(stats.free_entities, 0);
+    assert_eq!(stats.reserved_entities, 0);
+    assert_eq!(stats.entity_bytes, 5 * META);
+    let health = stats.component(Health::type_id()).unwrap();
+    assert_eq!(health.len, 2);
+    assert_eq!(health.capacity, 3);
+    assert_eq!(health.bytes, 4 * slot::<Health>());
+
+    registry.reserve::<Health>(100);
+    let stats = registry.stats();
+    assert_eq!(stats.entities, 4);
+    assert_eq!(stats.entity_capacity, 104);
+    assert_eq!(stats.entity_bytes, 105 * META);
+    let health = stats.component(Health::type_id()).unwrap();
+    assert_eq!(health.capacity, 104);
+    assert_eq!(health.bytes, 105 * slot::<Health>());
+
+    let before = registry.stats().bytes();
+    registry.shrink_to_fit();
+    let stats = registry.stats();
+    assert_eq!(stats.entity_capacity, 4);
+    assert_eq!(stats.component(Health::type_id()).unwrap().capacity, 3);
+    assert!(stats.bytes() < before);
+}
@@ -0,0 +1,223 @@
+//! Registry operations that touch type-erased storage, run under Miri by `.github/workflows/miri.yml`
+//! and locally with `cargo +nightly miri test --test storage`.
+
+use std::rc::Rc;
+use registry::{Component, Registry, Schema, FieldKind, Storage, uuid::Uuid, serde_json::json};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Label(String);
+
+impl Component for Label {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[derive(Clone)]
+struct Handle(Rc<u32>);
+
+impl Component for Handle {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x3)
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry.register_component::<Label>();
+    registry.register_runtime_component_cloned::<Handle>();
+    registry
+}
+
+#[test]
+fn attach_detach() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(10)).attach(Label("a".to_string())).id();
+    let b = registry.spawn().attach(Health(20)).id();
+    assert_eq!(registry.component::<Health>(a).map(|h| h.0), Some(10));
+    assert_eq!(registry.component_detach::<Health>(a), Some(Health(10)));
+    assert!(registry.component::<Health>(a).is_none());
+    registry.component_mut::<Health>(b).unwrap().0 += 1;
+    assert_eq!(registry.component::<Health>(b).map(|h| h.0), Some(21));
+    registry.despawn(b);
+    assert!(registry.component::<Health>(b).is_none());
+    assert_eq!(registry.component::<Label>(a).unwrap().0, "a");
+}
+
+#[test]
+fn iterate_while_borrowed() {
+    let mut registry = registry();
+    let a = registry.spawn().attach(Health(1)).id();
+    registry.spawn().attach(Health(2));
+    let held = registry.component_mut::<Health>(a).unwrap();
+    let healths = registry.components::<Health>();
+    let mut sum = 0;
+    for (_, mut health) in healths.iter_mut() {
+        health.0 += 1;
+        sum += health.0;
+    }
+    assert_eq!(sum, 3);
+    assert_eq!(healths.skipped(), 1);
+    drop(held);
+}
+
+#[test]
+fn clone() {
+    let mut registry = registry();
+    let handle = Rc::new(7);
+    let a = registry.spawn().attach(Label("a".to_string())).attach(Handle(handle.clone())).id();
+    let clone = registry.clone();
+    drop(registry);
+    assert_eq!(clone.component::<Label>(a).unwrap().0, "a");
+    assert_eq!(*clone.component::<Handle>(a).unwrap().0, 7);
+    assert_eq!(Rc::strong_count(&handle), 2);
+    drop(clone);
+    assert_eq!(Rc::strong_count(&handle), 1);
+}
+
+#[test]
+fn serialize_deserialize() {
+    let mut registry = registry();
+    let handle = Rc::new(1);
+    let a = registry.spawn().attach(Health(5)).attach(Label("a".to_string())).attach(Handle(handle.clone())).id();
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    registry.deserialize(&bytes);
+    assert_eq!(registry.component::<Health>(a).map(|h| h.0), Some(5));
+    assert!(registry.component::<Handle>(a).is_none());
+    assert_eq!(Rc::strong_count(&handle), 1);
+
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    assert_eq!(other.component::<Label>(a).unwrap().0, "a");
+}
+
+#[test]
+fn dynamic_and_singletons() {
+    let mut registry = registry();
+    let mana = Uuid::from_u128(0x10);
+    registry.register_dynamic_component(mana, Schema::new("Mana").field("value", FieldKind::Int));
+    registry.insert_singleton(Label("global".to_string()));
+    let a = registry.spawn().id();
+    registry.set_component_dyn(a, mana, json!({ "value": 3 })).unwrap();
+    let clone = registry.clone();
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    registry.clear();
+    registry.deserialize(&bytes);
+    assert_eq!(registry.component_dyn(a, mana), Some(json!({ "value": 3 })));
+    assert_eq!(clone.component_dyn(a, mana), Some(json!({ "value": 3 })));
+    assert_eq!(registry.remove_singleton::<Label>(), Some(Label("global".to_string())));
+}
+
+#[test]
+fn stream_dynamic_components() {
+    let mana = Uuid::from_u128(0x10);
+    let registry_with_mana = || {
+        let mut registry = registry();
+        registry.register_dynamic_component(mana, Schema::new("Mana").field("value", FieldKind::Int));
+        registry
+    };
+    let mut registry = registry_with_mana();
+    let a = registry.spawn().attach(Health(1)).id();
+    let b = registry.spawn().id();
+    registry.set_component_dyn(a, mana, json!({ "value": 3 })).unwrap();
+    registry.set_component_dyn(b, mana, json!({ "value": 4 })).unwrap();
+    let mut bytes = Vec::new();
+    registry.serialize_to(&mut bytes).unwrap();
+
+    let mut other = registry_with_mana();
+    other.deserialize_from(bytes.as_slice()).unwrap();
+    assert_eq!(other.component_dyn(a, mana), Some(json!({ "value": 3 })));
+    assert_eq!(other.component_dyn(b, mana), Some(json!({ "value": 4 })));
+    assert_eq!(other.component::<Health>(a).map(|h| h.0), Some(1));
+
+    assert!(other.deserialize_from(&bytes[..bytes.len() / 2]).is_err());
+    assert!(other.is_empty());
+    assert!(other.component_dyn(a, mana).is_none());
+    assert!(other.deserialize_from(&b"nope"[..]).is_err());
+}
+
+/// Written by the first release of `serialize`: `Health` 1, 2 and 3 with the second despawned.
+const LEGACY:&[u8] = &[4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 2, 0, 0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 36, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 3, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+#[test]
+fn load_first_release() {
+    let mut registry = registry();
+    registry.deserialize(LEGACY);
+    let ids:Vec<_> = registry.iter().collect();
+    assert_eq!(format!("{:?}", ids), "[EntityId(1v1), EntityId(3v1)]");
+    let health:Vec<_> = ids.iter().map(|id| registry.component::<Health>(*id).map(|h| h.0)).collect();
+    assert_eq!(health, [Some(1), Some(3)]);
+    let reused = registry.spawn().id();
+    assert_eq!(format!("{:?}", reused), "EntityId(2v3)");
+
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    assert!(other.contains(reused));
+    assert_eq!(other.len(), 3);
+}
+
+#[test]
+fn reserve_entities() {
+    let mut registry = registry();
+    let a = registry.spawn().id();
+    let b = registry.spawn().id();
+    registry.despawn(a);
+    let reserved = registry.reserve_entities(3);
+    assert_eq!(reserved.len(), 3);
+    assert!(reserved.iter().all(|id| !registry.contains(*id) && *id != a && *id != b));
+    assert_eq!(format!("{:?}", reserved[0]), "EntityId(1v3)");
+    let single = registry.reserve_entity();
+    assert!(!reserved.contains(&single));
+    assert_eq!(registry.len(), 1);
+
+    let c = registry.spawn().id();
+    assert!(reserved.iter().chain([&single, &c]).all(|id| registry.contains(*id)));
+    assert_eq!(registry.len(), 6);
+    assert!(registry.reserve_entities(0).is_empty());
+
+    let late = registry.reserve_entities(2);
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+    let mut other = self::registry();
+    other.deserialize(&bytes);
+    assert!(late.iter().all(|id| other.contains(*id)));
+}
+
+#[test]
+fn typed_access_is_checked() {
+    let mut storage = Storage::new::<Health>();
+    assert!(storage.get::<Label>().is_none());
+    assert!(storage.get_mut::<Health>().is_some());
+    let clone = storage.clone();
+    drop(storage);
+    assert!(clone.get::<Health>().is_some());
+}
+
+#[test]
+fn flags_are_read_only() {
+    let mut storage = Storage::new::<Health>();
+    assert_eq!(storage.name(), "Health");
+    assert!(storage.is_serialized());
+    assert!(!storage.is_replicated());
+    assert!(!storage.has_debug());
+    storage.set_debug::<Health>();
+    assert!(storage.clone().has_debug());
+    let runtime = Storage::new_runtime::<Handle>();
+    assert_eq!(runtime.name(), "Handle");
+    assert!(!runtime.is_serialized());
+}
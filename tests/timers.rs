@@ -0,0 +1,128 @@
+//! Delayed commands, including ones that survive saving and loading.
+
+use std::time::Duration;
+use registry::{Component, EntityId, Registry, TimedCommand, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Damage(EntityId, i32);
+
+impl TimedCommand for Damage {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x10)
+    }
+
+    fn run(self, registry:&mut Registry) {
+        if let Some(mut health) = registry.component_mut::<Health>(self.0) {
+            health.0 -= self.1;
+        }
+    }
+}
+
+/// Shares the id of `Damage` but not its layout.
+#[derive(Serialize, Deserialize)]
+struct Mismatched(String);
+
+impl TimedCommand for Mismatched {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x10)
+    }
+
+    fn run(self, _registry:&mut Registry) {
+    }
+}
+
+fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_component::<Health>();
+    registry
+}
+
+fn health(registry:&Registry, id:EntityId) -> i32 {
+    registry.component::<Health>(id).map(|health| health.0).unwrap()
+}
+
+#[test]
+fn runs_after_ticks_and_time() {
+    let mut registry = registry();
+    registry.register_command::<Damage>();
+    let id = registry.spawn().attach(Health(10)).id();
+    registry.push_delayed_command(2, Damage(id, 1));
+    registry.push_delayed_command(Duration::from_secs(1), Damage(id, 2));
+    let cancelled = registry.push_delayed(1, move |registry:&mut Registry| registry.despawn(id));
+    assert!(registry.cancel(cancelled));
+    assert!(!registry.cancel(cancelled));
+
+    registry.execute();
+    assert_eq!(health(&registry, id), 10);
+    registry.execute();
+    assert_eq!(health(&registry, id), 9);
+    registry.advance(Duration::from_secs(1));
+    registry.execute();
+    assert_eq!(health(&registry, id), 7);
+    assert_eq!(registry.pending_timers(), 0);
+}
+
+#[test]
+fn clone_keeps_timers() {
+    let mut registry = registry();
+    registry.register_command::<Damage>();
+    let id = registry.spawn().attach(Health(10)).id();
+    registry.push_delayed_command(1, Damage(id, 1));
+    registry.push_delayed(1, move |registry:&mut Registry| registry.component_mut::<Health>(id).unwrap().0 *= 2);
+
+    let mut clone = registry.clone();
+    assert_eq!(clone.pending_timers(), 2);
+    clone.execute();
+    registry.execute();
+    assert_eq!(health(&clone, id), 18);
+    assert_eq!(health(&registry, id), 18);
+}
+
+#[test]
+fn unregistered_commands_stay_pending() {
+    let mut registry = registry();
+    registry.register_command::<Damage>();
+    let id = registry.spawn().attach(Health(10)).id();
+    let timer = registry.push_delayed_command(1, Damage(id, 3));
+    let mut bytes = Vec::new();
+    registry.serialize(&mut bytes);
+
+    let mut loaded = self::registry();
+    loaded.deserialize(&bytes);
+    loaded.execute();
+    loaded.execute();
+    assert!(loaded.is_pending(timer));
+    assert_eq!(health(&loaded, id), 10);
+
+    loaded.register_command::<Damage>();
+    loaded.execute();
+    assert!(!loaded.is_pending(timer));
+    assert_eq!(health(&loaded, id), 7);
+}
+
+#[test]
+fn malformed_command_is_an_error() {
+    let mut registry = registry();
+    registry.register_command::<Damage>();
+    let id = registry.spawn().attach(Health(10)).id();
+    registry.push_delayed_command(1, Damage(id, 3));
+    let mut bytes = Vec::new();
+    registry.serialize_to(&mut bytes).unwrap();
+
+    let mut loaded = self::registry();
+    loaded.register_command::<Mismatched>();
+    loaded.deserialize_from(bytes.as_slice()).unwrap();
+    let err = loaded.try_execute().unwrap_err();
+    assert_eq!(err.command, Damage::type_id());
+    assert_eq!(loaded.pending_timers(), 0);
+}
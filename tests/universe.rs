@@ -0,0 +1,90 @@
+//! Worlds sharing registrations and entities moving between them.
+
+use registry::{Component, MoveError, Name, Universe, uuid::Uuid};
+use serde::{Serialize, Deserialize};
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+impl Component for Health {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x1)
+    }
+}
+
+struct Handle(u32);
+
+impl Component for Handle {
+    fn type_id() -> Uuid {
+        Uuid::from_u128(0x2)
+    }
+}
+
+#[test]
+fn moves_entity_with_components() {
+    let mut universe = Universe::new();
+    let a = universe.create_world();
+    universe.register_component::<Health>();
+    universe.register_runtime_component::<Handle>();
+    let b = universe.create_world();
+
+    let world = universe.world_mut(a).unwrap();
+    let id = world.spawn().attach(Health(3)).attach(Handle(7)).attach(Name::new("x")).id();
+    world.disable(id);
+    let moved = universe.move_entity(a, id, b).unwrap();
+
+    let source = universe.world(a).unwrap();
+    assert!(!source.contains(id));
+    assert_eq!(source.find_by_name("x"), None);
+    let target = universe.world(b).unwrap();
+    assert_eq!(target.component::<Health>(moved).map(|health| health.0), Some(3));
+    assert_eq!(target.component::<Handle>(moved).map(|handle| handle.0), Some(7));
+    assert_eq!(target.find_by_name("x"), Some(moved));
+    assert!(!target.is_enabled(moved));
+
+    assert_eq!(universe.move_entity(a, id, b), Err(MoveError::UnknownEntity(id)));
+    universe.remove_world(a);
+    assert_eq!(universe.move_entity(b, moved, a), Err(MoveError::UnknownWorld(a)));
+    assert!(universe.world(b).unwrap().contains(moved));
+}
+
+#[test]
+#[should_panic(expected = "Universe::register")]
+fn rejects_registration_on_a_single_world() {
+    let mut universe = Universe::new();
+    let world = universe.create_world();
+    universe.world_mut(world).unwrap().register_component::<Health>();
+}
+
+#[test]
+fn commands_target_a_world() {
+    let mut universe = Universe::new();
+    universe.register_component::<Health>();
+    let a = universe.create_world();
+    let b = universe.create_world();
+    let id = universe.world_mut(a).unwrap().spawn().attach(Health(1)).id();
+    universe.push(a, move |world| world.component_mut::<Health>(id).unwrap().0 = 2);
+    universe.push_universe(move |universe| {
+        universe.move_entity(a, id, b).unwrap();
+    });
+    universe.execute();
+    assert!(universe.world(a).unwrap().is_empty());
+    let moved = universe.world(b).unwrap().iter().next().unwrap();
+    assert_eq!(universe.world(b).unwrap().component::<Health>(moved).map(|health| health.0), Some(2));
+}
+
+#[test]
+fn move_keeps_keys_unique() {
+    let mut universe = Universe::new();
+    universe.register_component::<Health>();
+    universe.register(|world| {
+        world.create_unique_index::<Health, i32>(|health| health.0);
+    });
+    let a = universe.create_world();
+    let b = universe.create_world();
+    universe.world_mut(b).unwrap().spawn().attach(Health(3));
+    let id = universe.world_mut(a).unwrap().spawn().attach(Health(3)).id();
+    assert!(matches!(universe.move_entity(a, id, b), Err(MoveError::Conflict(_))));
+    assert_eq!(universe.world(a).unwrap().component::<Health>(id).map(|health| health.0), Some(3));
+    assert_eq!(universe.world(b).unwrap().len(), 1);
+}